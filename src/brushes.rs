@@ -0,0 +1,325 @@
+use crate::{
+    colors::{Color, WHITE},
+    image_converter::Image,
+};
+use serde::{Deserialize, Serialize};
+
+/// A brush size button
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Brush {
+    /// Center of the button
+    pub x: u32,
+    pub y: u32,
+    /// Diameter of the brush on the screen
+    pub size: u32,
+}
+
+impl Brush {
+    /// Reads brushes written like `x y size, x y size`
+    pub fn parse_list(text: &str) -> Result<Vec<Brush>, String> {
+        text.split(',')
+            .map(str::trim)
+            .filter(|brush| !brush.is_empty())
+            .map(|brush| {
+                let values = brush
+                    .split_whitespace()
+                    .map(|value| value.parse())
+                    .collect::<Result<Vec<u32>, _>>()
+                    .map_err(|_| format!("Invalid brush: {}", brush))?;
+
+                match values[..] {
+                    [x, y, size] if size > 0 => Ok(Brush { x, y, size }),
+                    _ => Err(format!("A brush needs X, Y and a size: {}", brush)),
+                }
+            })
+            .collect()
+    }
+
+    pub fn format_list(brushes: &[Brush]) -> String {
+        brushes
+            .iter()
+            .map(|brush| format!("{} {} {}", brush.x, brush.y, brush.size))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A stamp or a horizontal drag of a large brush, as offsets inside of the drawing area
+#[derive(PartialEq, Debug)]
+pub struct CoarseStroke {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+    pub color: Color,
+}
+
+/// Large brush strokes and the pixels they paint completely
+pub struct CoarsePlan {
+    width: u32,
+    covered: Vec<bool>,
+    pub strokes: Vec<CoarseStroke>,
+}
+
+impl CoarsePlan {
+    pub fn is_covered(&self, x: u32, y: u32) -> bool {
+        self.covered[(y * self.width + x) as usize]
+    }
+}
+
+// screen pixels of the pixels from `first` up to `last`, a pixel covers `step` screen pixels
+fn screen_range(first: u32, last: u32, step: f64) -> (u32, u32) {
+    (
+        (f64::from(first) * step).ceil() as u32,
+        (f64::from(last + 1) * step).ceil() as u32 - 1,
+    )
+}
+
+/// Finds square blocks of one color a round brush of `size` screen pixels fits into
+/// and merges neighbouring ones into drags. The brush never paints outside of the blocks,
+/// so the pixels it doesn't cover completely can still be drawn with a smaller brush later.
+/// Only pixels that are `pending` count as covered.
+pub fn plan_coarse<F>(
+    image: &Image,
+    size: u32,
+    step: f64,
+    area: (u32, u32),
+    pending: F,
+) -> CoarsePlan
+where
+    F: Fn(u32, u32) -> bool,
+{
+    let (width, height) = image.dimensions();
+    let mut plan = CoarsePlan {
+        width,
+        covered: vec![false; (width * height) as usize],
+        strokes: Vec::new(),
+    };
+
+    // a screen pixel gets painted if its distance to the brush is at most half the size
+    let reach = size / 2;
+    let block = (f64::from(2 * reach + 1) / step).ceil() as u32;
+    if block < 2 {
+        return plan;
+    }
+
+    // center of a block on the screen, None if the brush doesn't fit into it
+    // or the center is outside of the drawing area
+    let center = |first: u32, limit: u32| {
+        let (low, high) = screen_range(first, first + block - 1, step);
+        let center = (low + high) / 2;
+
+        if center >= low + reach && center + reach <= high && center < limit {
+            Some(center)
+        } else {
+            None
+        }
+    };
+
+    for block_y in 0..height / block {
+        let center_y = match center(block_y * block, area.1) {
+            Some(center_y) => center_y,
+            None => continue,
+        };
+        let mut block_x = 0;
+
+        while block_x < width / block {
+            let color = match uniform_color(image, block_x * block, block_y * block, block) {
+                Some(color) if color != WHITE && center(block_x * block, area.0).is_some() => color,
+                _ => {
+                    block_x += 1;
+                    continue;
+                }
+            };
+
+            // longest run of blocks with the same color
+            let first = block_x;
+            while block_x + 1 < width / block
+                && center((block_x + 1) * block, area.0).is_some()
+                && uniform_color(image, (block_x + 1) * block, block_y * block, block).as_ref()
+                    == Some(&color)
+            {
+                block_x += 1;
+            }
+            let last = block_x;
+            block_x += 1;
+
+            let start = (center(first * block, area.0).unwrap(), center_y);
+            let end = (center(last * block, area.0).unwrap(), center_y);
+
+            let mut covers_pending = false;
+            for y in block_y * block..(block_y + 1) * block {
+                for x in first * block..(last + 1) * block {
+                    if pending(x, y) && inside_stroke(x, y, step, start, end, size) {
+                        plan.covered[(y * width + x) as usize] = true;
+                        covers_pending = true;
+                    }
+                }
+            }
+
+            if covers_pending {
+                plan.strokes.push(CoarseStroke { start, end, color });
+            }
+        }
+    }
+
+    plan
+}
+
+// color of the block if all its pixels have the same one
+fn uniform_color(image: &Image, x: u32, y: u32, size: u32) -> Option<Color> {
+    let first = image.get_pixel(x, y);
+
+    for block_y in y..y + size {
+        for block_x in x..x + size {
+            if image.get_pixel(block_x, block_y) != first {
+                return None;
+            }
+        }
+    }
+
+    Some(Color {
+        r: first[0],
+        g: first[1],
+        b: first[2],
+    })
+}
+
+// whether every screen pixel of the pixel gets painted by a horizontal drag
+fn inside_stroke(x: u32, y: u32, step: f64, start: (u32, u32), end: (u32, u32), size: u32) -> bool {
+    let (low_x, high_x) = screen_range(x, x, step);
+    let (low_y, high_y) = screen_range(y, y, step);
+
+    (low_y..=high_y).all(|screen_y| {
+        (low_x..=high_x).all(|screen_x| {
+            let distance_x = i64::from(start.0.saturating_sub(screen_x))
+                .max(i64::from(screen_x.saturating_sub(end.0)));
+            let distance_y = i64::from(screen_y) - i64::from(start.1);
+
+            4 * (distance_x * distance_x + distance_y * distance_y) <= i64::from(size * size)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn rgb(color: &Color) -> Rgb<u8> {
+        Rgb([color.r, color.g, color.b])
+    }
+
+    fn covered(plan: &CoarsePlan, image: &Image) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| if plan.is_covered(x, y) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_brush_lists() {
+        let brushes = Brush::parse_list("10 20 4, 30 20 10,").unwrap();
+
+        assert_eq!(
+            brushes,
+            vec![
+                Brush {
+                    x: 10,
+                    y: 20,
+                    size: 4
+                },
+                Brush {
+                    x: 30,
+                    y: 20,
+                    size: 10
+                },
+            ]
+        );
+        assert_eq!(Brush::format_list(&brushes), "10 20 4, 30 20 10");
+        assert_eq!(Brush::parse_list(""), Ok(Vec::new()));
+        assert!(Brush::parse_list("10 20").is_err());
+        assert!(Brush::parse_list("10 20 x").is_err());
+    }
+
+    #[test]
+    fn stamps_blocks_the_brush_fits_into() {
+        let image = ImageBuffer::from_pixel(5, 5, rgb(&BLACK));
+
+        let plan = plan_coarse(&image, 12, 3.0, (100, 100), |_, _| true);
+
+        assert_eq!(
+            plan.strokes,
+            vec![CoarseStroke {
+                start: (7, 7),
+                end: (7, 7),
+                color: BLACK,
+            }]
+        );
+        // the edges of the block are outside of the round brush
+        assert_eq!(
+            covered(&plan, &image),
+            vec![".....", ".###.", ".###.", ".###.", "....."]
+        );
+    }
+
+    #[test]
+    fn merges_blocks_into_drags() {
+        let image = ImageBuffer::from_fn(
+            15,
+            5,
+            |x, _| {
+                if x < 10 {
+                    rgb(&LIGHT_RED)
+                } else {
+                    rgb(&BLACK)
+                }
+            },
+        );
+
+        let plan = plan_coarse(&image, 12, 3.0, (100, 100), |_, _| true);
+
+        assert_eq!(
+            plan.strokes,
+            vec![
+                CoarseStroke {
+                    start: (7, 7),
+                    end: (22, 7),
+                    color: LIGHT_RED,
+                },
+                CoarseStroke {
+                    start: (37, 7),
+                    end: (37, 7),
+                    color: BLACK,
+                },
+            ]
+        );
+        assert_eq!(covered(&plan, &image)[1..4], [".########..###."; 3]);
+    }
+
+    #[test]
+    fn skips_white_mixed_done_and_hidden_blocks() {
+        let image = ImageBuffer::from_fn(20, 5, |x, y| match (x, y) {
+            (0..=4, _) => rgb(&WHITE),
+            (7, 3) => rgb(&BLACK),
+            _ => rgb(&LIGHT_BLUE),
+        });
+
+        // the third block is already drawn, the fourth is outside of the drawing area
+        let plan = plan_coarse(&image, 12, 3.0, (45, 100), |x, _| !(10..15).contains(&x));
+
+        assert!(plan.strokes.is_empty());
+        assert!(covered(&plan, &image).iter().all(|row| !row.contains('#')));
+    }
+
+    #[test]
+    fn small_brushes_plan_nothing() {
+        let image = ImageBuffer::from_pixel(4, 4, rgb(&BLACK));
+
+        assert!(plan_coarse(&image, 3, 3.0, (100, 100), |_, _| true)
+            .strokes
+            .is_empty());
+    }
+}
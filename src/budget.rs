@@ -0,0 +1,252 @@
+use crate::{
+    colors::Color,
+    image_converter::{self, Dithering, Image},
+    settings::Settings,
+    simulator::{self, Stats},
+};
+use image::DynamicImage;
+use std::{fmt, time::Duration};
+
+// every attempt shrinks the drawing to this part of the last one
+const SCALE_STEP: f64 = 0.8;
+/// Drawings don't get smaller than this, they wouldn't be recognisable anymore
+const MIN_SCALE: f64 = 0.2;
+
+/// A converted image with the settings that were lowered until it fits into the time budget
+pub struct Fit {
+    pub image: Image,
+    /// The simulated drawing
+    pub canvas: Image,
+    pub settings: Settings,
+    pub stats: Stats,
+    /// What was lowered, empty if the drawing fits as it is
+    pub lowered: Vec<String>,
+    /// False if it takes too long even with the lowest quality
+    pub fits: bool,
+}
+
+impl fmt::Display for Fit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.stats)?;
+
+        if !self.lowered.is_empty() {
+            write!(f, " - lowered to {}", self.lowered.join(", "))?;
+        }
+        if !self.fits {
+            write!(f, " - longer than the time budget")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Fit {
+    /// The colors the palette was restricted to for the image and the time that saves,
+    /// None if the image may use the whole palette
+    pub fn restriction(&self, image: &DynamicImage) -> Option<Restriction> {
+        if self.settings.custom_colors().is_some() {
+            return None;
+        }
+        self.settings.max_colors()?;
+
+        let palette = image_converter::colors_for_image(image, &self.settings)?;
+        let mut all_colors = self.settings.clone();
+        all_colors.max_colors = Some(0);
+        let (_, _, stats) = attempt(image, &all_colors);
+
+        Some(Restriction {
+            colors: palette.colors().cloned().collect(),
+            palette_size: self.settings.palette().ok()?.colors().count(),
+            saved: stats
+                .estimated_time
+                .saturating_sub(self.stats.estimated_time),
+        })
+    }
+}
+
+/// The best colors of the palette for an image
+pub struct Restriction {
+    pub colors: Vec<Color>,
+    pub palette_size: usize,
+    /// How much faster the drawing is than with all colors
+    pub saved: Duration,
+}
+
+impl fmt::Display for Restriction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let colors: Vec<String> = self.colors.iter().map(Color::to_string).collect();
+
+        write!(
+            f,
+            "{} of {} colors ({}), about {:.1}s faster",
+            self.colors.len(),
+            self.palette_size,
+            colors.join(" "),
+            self.saved.as_secs_f64()
+        )
+    }
+}
+
+// converts and simulates the drawing with the settings
+fn attempt(image: &DynamicImage, settings: &Settings) -> (Image, Image, Stats) {
+    let converted = image_converter::convert_with_settings(image.clone(), settings);
+    let (canvas, stats) = simulator::simulate(settings, &converted);
+
+    (converted, canvas, stats)
+}
+
+/// Converts the image and estimates how long drawing it takes. If that's longer than
+/// the time budget, the dithering noise is dropped first and then the drawing is
+/// made smaller until it fits.
+pub fn fit(image: &DynamicImage, settings: &Settings) -> Fit {
+    let mut settings = settings.clone();
+    let mut lowered = Vec::new();
+    let (mut converted, mut canvas, mut stats) = attempt(image, &settings);
+
+    let budget = match settings.time_budget() {
+        Some(budget) => budget,
+        None => {
+            return Fit {
+                image: converted,
+                canvas,
+                settings,
+                stats,
+                lowered,
+                fits: true,
+            }
+        }
+    };
+
+    // noise of the dithering breaks up strokes, but solid colors don't always draw faster
+    if stats.estimated_time > budget && settings.dithering() != Dithering::None {
+        let mut plain = settings.clone();
+        plain.dithering = Some(Dithering::None);
+        plain.dither = false;
+
+        let (plain_converted, plain_canvas, plain_stats) = attempt(image, &plain);
+        if plain_stats.estimated_time < stats.estimated_time {
+            settings = plain;
+            converted = plain_converted;
+            canvas = plain_canvas;
+            stats = plain_stats;
+            lowered.push("no dithering".to_string());
+        }
+    }
+
+    let original_scale = settings.scale;
+    while stats.estimated_time > budget && settings.scale * SCALE_STEP >= MIN_SCALE {
+        settings.scale *= SCALE_STEP;
+
+        let (smaller_converted, smaller_canvas, smaller_stats) = attempt(image, &settings);
+        converted = smaller_converted;
+        canvas = smaller_canvas;
+        stats = smaller_stats;
+    }
+    if settings.scale != original_scale {
+        lowered.push(format!("scale {:.2}", settings.scale));
+    }
+
+    Fit {
+        image: converted,
+        canvas,
+        settings,
+        stats,
+        lowered,
+        fits: stats.estimated_time <= budget,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drawer::Strokes;
+    use image::{ImageBuffer, Rgb};
+    use std::time::Duration;
+
+    fn settings(time_budget: f64) -> Settings {
+        Settings {
+            drawing_x: 100,
+            drawing_y: 200,
+            drawing_width: 60,
+            drawing_height: 60,
+            color_x: 10,
+            color_y: 20,
+            color_width: 4,
+            color_height: 4,
+            checkerboard: false,
+            strokes: Some(Strokes::Pixels),
+            delay: 10.0,
+            time_budget: Some(time_budget),
+            ..Settings::default()
+        }
+    }
+
+    // a grey gradient that needs dithering
+    fn gradient() -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_fn(20, 20, |x, _| {
+            let grey = (x * 10) as u8;
+            Rgb([grey, grey, grey])
+        }))
+    }
+
+    #[test]
+    fn keeps_drawings_that_fit() {
+        let fit = fit(&gradient(), &settings(0.0));
+
+        assert!(fit.fits);
+        assert!(fit.lowered.is_empty());
+        assert_eq!(fit.settings.scale, 1.0);
+        assert_eq!(fit.image.dimensions(), (20, 20));
+
+        let unlimited = super::fit(&gradient(), &settings(60.0));
+        assert!(unlimited.lowered.is_empty());
+        assert_eq!(unlimited.stats, fit.stats);
+    }
+
+    #[test]
+    fn lowers_the_quality_until_it_fits() {
+        let full = fit(&gradient(), &settings(0.0));
+        let budget = full.stats.estimated_time / 3;
+
+        let fit = fit(&gradient(), &settings(budget.as_secs_f64()));
+
+        assert!(fit.fits);
+        assert!(fit.stats.estimated_time <= budget);
+        assert!(fit.settings.scale < 1.0);
+        assert!(fit.image.width() < 20);
+        assert_eq!(
+            fit.lowered.last().unwrap(),
+            &format!("scale {:.2}", fit.settings.scale)
+        );
+    }
+
+    #[test]
+    fn reports_drawings_that_never_fit() {
+        let fit = fit(&gradient(), &settings(0.001));
+
+        assert!(!fit.fits);
+        assert!(fit.settings.scale >= MIN_SCALE);
+        assert!(fit.stats.estimated_time > Duration::from_millis(1));
+        assert!(fit.to_string().ends_with("longer than the time budget"));
+    }
+
+    #[test]
+    fn restricts_the_colors_of_the_image() {
+        let mut settings = settings(0.0);
+        assert!(fit(&gradient(), &settings)
+            .restriction(&gradient())
+            .is_none());
+
+        settings.max_colors = Some(2);
+        let fit = fit(&gradient(), &settings);
+        let restriction = fit.restriction(&gradient()).unwrap();
+
+        assert_eq!(restriction.colors.len(), 2);
+        assert_eq!(restriction.palette_size, 22);
+        assert_eq!(fit.stats.color_switches, 1);
+        assert!(restriction.saved > Duration::from_secs(0));
+        assert!(restriction
+            .to_string()
+            .starts_with("2 of 22 colors (#ffffff "));
+    }
+}
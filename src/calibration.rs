@@ -0,0 +1,410 @@
+use crate::{
+    colors::{Color, WHITE},
+    drawer::Box,
+    image_converter::Image,
+    palette::Palette,
+    settings::Settings,
+};
+use image::Rgb;
+
+// screenshots of the browser might not have exact colors
+const TOLERANCE: i32 = 8;
+// the canvas has to be at least this big to not mistake the white swatch for it
+const MIN_CANVAS_SIZE: u32 = 100;
+// swatches smaller than this are ignored
+const MIN_SWATCH_SIZE: u32 = 4;
+// how many swatches may be hidden, for example by the mouse
+const MAX_MISSING_SWATCHES: usize = 2;
+
+/// Positions that were found on a screenshot of the game
+#[derive(PartialEq, Eq, Debug)]
+pub struct Calibration {
+    pub canvas: Box,
+    pub color_box: Box,
+}
+
+impl Calibration {
+    pub fn apply(&self, settings: &mut Settings) {
+        settings.drawing_x = self.canvas.x;
+        settings.drawing_y = self.canvas.y;
+        settings.drawing_width = self.canvas.width;
+        settings.drawing_height = self.canvas.height;
+
+        settings.color_x = self.color_box.x;
+        settings.color_y = self.color_box.y;
+        settings.color_width = self.color_box.width;
+        settings.color_height = self.color_box.height;
+    }
+}
+
+/// Finds the canvas and the color palette on a screenshot,
+/// the canvas has to be empty for this to work
+pub fn calibrate(screenshot: &Image, palette: &Palette) -> Result<Calibration, String> {
+    let canvas = find_canvas(screenshot).ok_or("Could not find the canvas")?;
+    let landmarks = Landmarks::of(palette)
+        .ok_or("The palette needs at least three swatches in its first row")?;
+    let color_box = find_color_box(screenshot, palette, &landmarks)
+        .ok_or("Could not find the color palette")?;
+
+    Ok(Calibration { canvas, color_box })
+}
+
+pub fn calibrate_file(path: &str, palette: &Palette) -> Result<Calibration, String> {
+    let screenshot = image::open(path)
+        .map_err(|err| format!("Failed to open {}: {}", path, err))?
+        .to_rgb8();
+
+    calibrate(&screenshot, palette)
+}
+
+// swatches the palette is found by, the first one is usually white like the canvas
+struct Landmarks<'a> {
+    // second in the first row
+    anchor: &'a Color,
+    // right of the anchor
+    right: &'a Color,
+    // below the anchor, None if there's only one row
+    below: Option<&'a Color>,
+}
+
+impl<'a> Landmarks<'a> {
+    fn of(palette: &'a Palette) -> Option<Landmarks<'a>> {
+        Some(Landmarks {
+            anchor: palette.get(1, 0)?,
+            right: palette.get(2, 0)?,
+            below: palette.get(1, 1),
+        })
+    }
+}
+
+/// Whether a pixel of a screenshot has the color, give or take the tolerance
+pub fn matches(pixel: &Rgb<u8>, color: &Color) -> bool {
+    (i32::from(pixel[0]) - i32::from(color.r)).abs() <= TOLERANCE
+        && (i32::from(pixel[1]) - i32::from(color.g)).abs() <= TOLERANCE
+        && (i32::from(pixel[2]) - i32::from(color.b)).abs() <= TOLERANCE
+}
+
+// the canvas is the largest white rectangle on the screen
+fn find_canvas(screenshot: &Image) -> Option<Box> {
+    let width = screenshot.width() as usize;
+
+    // amount of white pixels above and including the current row
+    let mut heights = vec![0u32; width];
+    let mut best: Option<Box> = None;
+    let mut best_area = 0;
+
+    for y in 0..screenshot.height() {
+        for (x, height) in heights.iter_mut().enumerate() {
+            if matches(screenshot.get_pixel(x as u32, y), &WHITE) {
+                *height += 1;
+            } else {
+                *height = 0;
+            }
+        }
+
+        // largest rectangle in the histogram of this row
+        let mut stack: Vec<usize> = Vec::new();
+        for x in 0..=width {
+            let current = if x < width { heights[x] } else { 0 };
+
+            while let Some(&top) = stack.last() {
+                if heights[top] < current {
+                    break;
+                }
+                stack.pop();
+
+                let left = stack.last().map_or(0, |&left| left + 1);
+                let rectangle = Box {
+                    x: left as u32,
+                    y: y + 1 - heights[top],
+                    width: (x - left) as u32,
+                    height: heights[top],
+                };
+
+                let area = rectangle.width * rectangle.height;
+                if area > best_area {
+                    best = Some(rectangle);
+                    best_area = area;
+                }
+            }
+
+            stack.push(x);
+        }
+    }
+
+    best.filter(|best| best.width >= MIN_CANVAS_SIZE && best.height >= MIN_CANVAS_SIZE)
+}
+
+// looks for the anchor swatch and checks whether the rest of the palette is around it
+fn find_color_box(screenshot: &Image, palette: &Palette, landmarks: &Landmarks) -> Option<Box> {
+    let mut visited = vec![false; (screenshot.width() * screenshot.height()) as usize];
+
+    for y in 0..screenshot.height() {
+        for x in 0..screenshot.width() {
+            let index = (y * screenshot.width() + x) as usize;
+            if visited[index] || !matches(screenshot.get_pixel(x, y), landmarks.anchor) {
+                continue;
+            }
+
+            let swatch = flood_fill(screenshot, &mut visited, x, y, landmarks.anchor);
+            if swatch.width < MIN_SWATCH_SIZE || swatch.height < MIN_SWATCH_SIZE {
+                continue;
+            }
+
+            if let Some(color_box) = color_box_around(screenshot, palette, landmarks, swatch) {
+                return Some(color_box);
+            }
+        }
+    }
+
+    None
+}
+
+// bounding box of all connected pixels with the given color
+fn flood_fill(screenshot: &Image, visited: &mut [bool], x: u32, y: u32, color: &Color) -> Box {
+    let (width, height) = screenshot.dimensions();
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (x, y, x, y);
+
+    let mut stack = vec![(x, y)];
+    visited[(y * width + x) as usize] = true;
+
+    while let Some((x, y)) = stack.pop() {
+        min_x = min_x.min(x);
+        min_y = min_y.min(y);
+        max_x = max_x.max(x);
+        max_y = max_y.max(y);
+
+        let neighbours = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+
+        for &(nx, ny) in neighbours.iter() {
+            if nx >= width || ny >= height {
+                continue;
+            }
+
+            let index = (ny * width + nx) as usize;
+            if !visited[index] && matches(screenshot.get_pixel(nx, ny), color) {
+                visited[index] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+
+    Box {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x + 1,
+        height: max_y - min_y + 1,
+    }
+}
+
+fn color_box_around(
+    screenshot: &Image,
+    palette: &Palette,
+    landmarks: &Landmarks,
+    anchor: Box,
+) -> Option<Box> {
+    let center_x = anchor.x + anchor.width / 2;
+    let center_y = anchor.y + anchor.height / 2;
+
+    // distance between two swatches, they might have a gap between them
+    let pitch_x = (anchor.x + anchor.width..anchor.x + anchor.width * 3)
+        .take_while(|&x| x < screenshot.width())
+        .find(|&x| matches(screenshot.get_pixel(x, center_y), landmarks.right))?
+        - anchor.x;
+    let pitch_y = match landmarks.below {
+        Some(below) => {
+            (anchor.y + anchor.height..anchor.y + anchor.height * 3)
+                .take_while(|&y| y < screenshot.height())
+                .find(|&y| matches(screenshot.get_pixel(center_x, y), below))?
+                - anchor.y
+        }
+        None => anchor.height,
+    };
+
+    let color_box = Box {
+        x: anchor.x.checked_sub(pitch_x)?,
+        y: anchor.y,
+        width: pitch_x,
+        height: pitch_y,
+    };
+
+    let matching = palette
+        .rows
+        .iter()
+        .enumerate()
+        .flat_map(|(row, colors)| {
+            colors
+                .iter()
+                .enumerate()
+                .map(move |(column, color)| (column, row, color))
+        })
+        .filter(|&(column, row, color)| {
+            let x = color_box.x + color_box.width * column as u32 + anchor.width / 2;
+            let y = color_box.y + color_box.height * row as u32 + anchor.height / 2;
+
+            x < screenshot.width()
+                && y < screenshot.height()
+                && matches(screenshot.get_pixel(x, y), color)
+        })
+        .count();
+
+    if matching + MAX_MISSING_SWATCHES >= palette.colors().count() {
+        Some(color_box)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::LIGHT_GREY;
+    use image::ImageBuffer;
+
+    const BACKGROUND: Rgb<u8> = Rgb([40, 80, 160]);
+
+    fn fill(screenshot: &mut Image, area: Box, color: &Color) {
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                screenshot.put_pixel(x, y, Rgb([color.r, color.g, color.b]));
+            }
+        }
+    }
+
+    // fake game screen with a canvas and a palette with 20px swatches
+    fn screenshot(canvas: Box, palette_x: u32, palette_y: u32, gap: u32) -> Image {
+        let mut screenshot = ImageBuffer::from_pixel(640, 480, BACKGROUND);
+
+        fill(&mut screenshot, canvas, &WHITE);
+
+        for (row, colors) in Palette::skribbl().rows.iter().enumerate() {
+            for (column, color) in colors.iter().enumerate() {
+                let swatch = Box {
+                    x: palette_x + (20 + gap) * column as u32,
+                    y: palette_y + (20 + gap) * row as u32,
+                    width: 20,
+                    height: 20,
+                };
+                fill(&mut screenshot, swatch, color);
+            }
+        }
+
+        screenshot
+    }
+
+    #[test]
+    fn finds_canvas_and_palette() {
+        let canvas = Box {
+            x: 50,
+            y: 30,
+            width: 400,
+            height: 300,
+        };
+
+        let calibration = calibrate(&screenshot(canvas, 60, 400, 0), &Palette::skribbl()).unwrap();
+
+        assert_eq!(calibration.canvas, canvas);
+        assert_eq!(
+            calibration.color_box,
+            Box {
+                x: 60,
+                y: 400,
+                width: 20,
+                height: 20,
+            }
+        );
+    }
+
+    #[test]
+    fn handles_gaps_between_swatches() {
+        let canvas = Box {
+            x: 10,
+            y: 10,
+            width: 300,
+            height: 200,
+        };
+
+        let calibration = calibrate(&screenshot(canvas, 100, 300, 2), &Palette::skribbl()).unwrap();
+
+        assert_eq!(
+            calibration.color_box,
+            Box {
+                x: 100,
+                y: 300,
+                width: 22,
+                height: 22,
+            }
+        );
+    }
+
+    #[test]
+    fn ignores_drawings_next_to_the_canvas() {
+        let canvas = Box {
+            x: 50,
+            y: 30,
+            width: 400,
+            height: 300,
+        };
+        let mut screenshot = screenshot(canvas, 60, 400, 0);
+
+        // a white box that's smaller than the canvas and a stray grey one
+        let other = Box {
+            x: 500,
+            y: 30,
+            width: 100,
+            height: 100,
+        };
+        fill(&mut screenshot, other, &WHITE);
+        let stray = Box {
+            x: 520,
+            y: 200,
+            width: 20,
+            height: 20,
+        };
+        fill(&mut screenshot, stray, &LIGHT_GREY);
+
+        let calibration = calibrate(&screenshot, &Palette::skribbl()).unwrap();
+
+        assert_eq!(calibration.canvas, canvas);
+        assert_eq!(calibration.color_box.x, 60);
+    }
+
+    #[test]
+    fn reads_screenshot_files() {
+        let canvas = Box {
+            x: 50,
+            y: 30,
+            width: 400,
+            height: 300,
+        };
+        let path = std::env::temp_dir().join("skribbliobot_calibration_test.png");
+        screenshot(canvas, 60, 400, 0).save(&path).unwrap();
+
+        let calibration = calibrate_file(path.to_str().unwrap(), &Palette::skribbl());
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(calibration.unwrap().canvas, canvas);
+    }
+
+    #[test]
+    fn fails_without_palette() {
+        let mut screenshot = ImageBuffer::from_pixel(640, 480, BACKGROUND);
+        let canvas = Box {
+            x: 50,
+            y: 30,
+            width: 400,
+            height: 300,
+        };
+        fill(&mut screenshot, canvas, &WHITE);
+
+        assert_eq!(
+            calibrate(&screenshot, &Palette::skribbl()),
+            Err("Could not find the color palette".into())
+        );
+    }
+}
@@ -0,0 +1,781 @@
+use crate::{
+    brushes::Brush,
+    budget, calibration,
+    color_distance::ColorDistance,
+    desktop::{self, ClickType},
+    drawer::{DrawEvent, DrawOrder, Drawer, PointerGuard, Strokes},
+    game::Profile,
+    hotkeys,
+    image_converter::{self, Dithering, Sketch},
+    image_downloader::{DownloadImageError, ImageDownloader},
+    palette::Palette,
+    session::{Recorder, Session},
+    settings::Settings,
+    simulator::{self, Stats},
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use image::DynamicImage;
+use std::{
+    io::{self, Write},
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+pub fn app() -> App<'static, 'static> {
+    App::new("skribbliobot")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("A drawing bot for skribbl.io, starts the GUI when run without a subcommand")
+        .subcommand(
+            SubCommand::with_name("draw")
+                .about("Draws an image without opening the GUI")
+                .args(&conversion_args())
+                .args(&drawing_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Continues the last drawing that was cancelled, with the same settings"),
+        )
+        .subcommand(
+            SubCommand::with_name("preview")
+                .about("Writes the converted image to a file instead of drawing it")
+                .args(&conversion_args())
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .default_value("preview.png")
+                        .help("Where to write the preview"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("dry-run")
+                .about("Simulates a drawing and writes the expected result to a file")
+                .args(&conversion_args())
+                .args(&drawing_args())
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("FILE")
+                        .default_value("dry-run.png")
+                        .help("Where to write the simulated canvas"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("benchmark")
+                .about("Simulates images with and without ordering the strokes and compares them")
+                .args(&conversion_args())
+                .args(&drawing_args())
+                .arg(
+                    Arg::with_name("images")
+                        .value_name("IMAGE")
+                        .multiple(true)
+                        .required_unless("image")
+                        .help("More images to compare"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("search")
+                .about("Downloads image search results")
+                .arg(
+                    Arg::with_name("query")
+                        .value_name("QUERY")
+                        .required(true)
+                        .help("What to search for"),
+                )
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .short("n")
+                        .value_name("N")
+                        .default_value("5")
+                        .help("How many images to download"),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .long("output")
+                        .short("o")
+                        .value_name("DIR")
+                        .default_value(".")
+                        .help("Directory to save the images in"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("calibrate")
+                .about("Shows or sets the canvas and color palette positions")
+                .arg(profile_arg().help("The game to draw in, its palette and buttons are used"))
+                .arg(
+                    Arg::with_name("auto")
+                        .long("auto")
+                        .conflicts_with_all(&["screenshot", "canvas", "palette"])
+                        .help("Find the positions on the screen, the canvas has to be empty"),
+                )
+                .arg(
+                    Arg::with_name("screenshot")
+                        .long("screenshot")
+                        .value_name("FILE")
+                        .conflicts_with_all(&["canvas", "palette"])
+                        .help("Find the positions on a screenshot of the game"),
+                )
+                .arg(
+                    Arg::with_name("canvas")
+                        .long("canvas")
+                        .value_names(&["X", "Y", "WIDTH", "HEIGHT"])
+                        .help("Position and size of the drawing canvas"),
+                )
+                .arg(
+                    Arg::with_name("palette")
+                        .long("palette")
+                        .value_names(&["X", "Y", "WIDTH", "HEIGHT"])
+                        .help("Position and size of the white color box"),
+                )
+                .arg(
+                    Arg::with_name("brush-tool")
+                        .long("brush-tool")
+                        .value_names(&["X", "Y"])
+                        .help("Center of the brush button"),
+                )
+                .arg(
+                    Arg::with_name("fill-tool")
+                        .long("fill-tool")
+                        .value_names(&["X", "Y"])
+                        .help("Center of the bucket button"),
+                )
+                .arg(
+                    Arg::with_name("brush")
+                        .long("brush")
+                        .value_names(&["X", "Y", "SIZE"])
+                        .multiple(true)
+                        .help("Center of a brush size button and the brush size, once per brush"),
+                ),
+        )
+}
+
+// skribbl, garticphone, drawize, pinturillo or a profile file
+fn profile_arg() -> Arg<'static, 'static> {
+    Arg::with_name("profile")
+        .long("profile")
+        .value_name("GAME|FILE")
+}
+
+fn conversion_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("image")
+            .long("image")
+            .short("i")
+            .value_name("FILE")
+            .help("Image to draw, uses the clipboard if not given"),
+        Arg::with_name("dither")
+            .long("dither")
+            .overrides_with_all(&["no-dither", "dithering"])
+            .help("Enable Floyd-Steinberg dithering"),
+        Arg::with_name("no-dither")
+            .long("no-dither")
+            .overrides_with_all(&["dither", "dithering"])
+            .help("Disable dithering"),
+        Arg::with_name("dithering")
+            .long("dithering")
+            .value_name("ALGORITHM")
+            .overrides_with_all(&["dither", "no-dither"])
+            .possible_values(&[
+                "none",
+                "floyd-steinberg",
+                "atkinson",
+                "sierra",
+                "jarvis-judice-ninke",
+                "bayer2",
+                "bayer4",
+                "bayer8",
+                "blue-noise",
+            ])
+            .help("How colors that are not in the palette are approximated"),
+        Arg::with_name("color-distance")
+            .long("color-distance")
+            .value_name("METRIC")
+            .possible_values(&["rgb", "cielab", "ciede2000", "oklab"])
+            .help("How the nearest palette color is found"),
+        Arg::with_name("palette-file")
+            .long("palette-file")
+            .value_name("FILE")
+            .help("JSON file with the colors of the game instead of the ones of its profile"),
+        profile_arg().help("Use the palette and buttons of another game for this drawing"),
+        Arg::with_name("custom-colors")
+            .long("custom-colors")
+            .value_name("COUNT")
+            .help("Pick this many colors for the image and type them into the color picker, 0 uses the palette"),
+        Arg::with_name("max-colors")
+            .long("max-colors")
+            .value_name("COUNT")
+            .help("Only use this many palette colors that fit the image best, 0 uses all of them"),
+        Arg::with_name("sketch")
+            .long("sketch")
+            .value_name("LINES")
+            .possible_values(&["off", "black", "colored"])
+            .help("Draw only the outlines, in black or in the color of their region"),
+        Arg::with_name("grayscale")
+            .long("grayscale")
+            .overrides_with("no-grayscale")
+            .help("Draw in grayscale"),
+        Arg::with_name("no-grayscale")
+            .long("no-grayscale")
+            .overrides_with("grayscale")
+            .help("Draw in color"),
+        Arg::with_name("scale")
+            .long("scale")
+            .value_name("FACTOR")
+            .help("Drawing scale, 1.0 fills the canvas"),
+    ]
+}
+
+fn drawing_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("checkerboard")
+            .long("checkerboard")
+            .overrides_with("no-checkerboard")
+            .help("Draw in two checkerboard passes"),
+        Arg::with_name("no-checkerboard")
+            .long("no-checkerboard")
+            .overrides_with("checkerboard")
+            .help("Draw in a single pass"),
+        Arg::with_name("fill")
+            .long("fill")
+            .overrides_with("no-fill")
+            .help("Fill large regions with the bucket, needs the tool positions"),
+        Arg::with_name("no-fill")
+            .long("no-fill")
+            .overrides_with("fill")
+            .help("Draw every pixel with the brush"),
+        Arg::with_name("optimize")
+            .long("optimize")
+            .overrides_with("no-optimize")
+            .help("Draw every color in one go and order its strokes by distance"),
+        Arg::with_name("no-optimize")
+            .long("no-optimize")
+            .overrides_with("optimize")
+            .help("Draw the strokes in the order they were found"),
+        Arg::with_name("verify")
+            .long("verify")
+            .overrides_with("no-verify")
+            .help("Capture the canvas after drawing and draw the pixels that were missed again"),
+        Arg::with_name("no-verify")
+            .long("no-verify")
+            .overrides_with("verify")
+            .help("Trust that every click arrived"),
+        Arg::with_name("strokes")
+            .long("strokes")
+            .value_name("STROKES")
+            .possible_values(&["pixels", "rows", "columns"])
+            .help("How pixels of the same color are merged into drags, needs --no-checkerboard"),
+        Arg::with_name("order")
+            .long("order")
+            .value_name("ORDER")
+            .possible_values(&["brightness", "importance"])
+            .help("Draw darkest colors first or edges and dark strokes first"),
+        Arg::with_name("pointer-guard")
+            .long("pointer-guard")
+            .value_name("GUARD")
+            .possible_values(&["off", "pause", "abort"])
+            .help("Pause or cancel the drawing when someone else moves the mouse"),
+        Arg::with_name("delay")
+            .long("delay")
+            .value_name("MS")
+            .help("Delay after every click"),
+        Arg::with_name("step")
+            .long("step")
+            .value_name("PIXELS")
+            .help(
+            "Distance between two drawn pixels, the image is resized to the canvas divided by it",
+        ),
+        Arg::with_name("time-budget")
+            .long("time-budget")
+            .value_name("SECONDS")
+            .help("Lower the quality until the drawing takes at most this long, 0 for no limit"),
+    ]
+}
+
+/// Runs the given subcommand
+pub fn run(matches: &ArgMatches) -> Result<(), String> {
+    let mut settings =
+        Settings::load().map_err(|err| format!("Failed to read settings: {}", err))?;
+
+    match matches.subcommand() {
+        ("draw", Some(matches)) => {
+            apply_overrides(&mut settings, matches)?;
+            draw(&settings, matches)
+        }
+        ("resume", Some(_)) => resume(&settings),
+        ("preview", Some(matches)) => {
+            apply_overrides(&mut settings, matches)?;
+            preview(&settings, matches)
+        }
+        ("dry-run", Some(matches)) => {
+            apply_overrides(&mut settings, matches)?;
+            dry_run(&settings, matches)
+        }
+        ("benchmark", Some(matches)) => {
+            apply_overrides(&mut settings, matches)?;
+            benchmark(&settings, matches)
+        }
+        ("search", Some(matches)) => search(matches),
+        ("calibrate", Some(matches)) => calibrate(&mut settings, matches),
+        _ => unreachable!(),
+    }
+}
+
+fn draw(settings: &Settings, matches: &ArgMatches) -> Result<(), String> {
+    if !settings.is_ready() {
+        return Err("Please enter positions with `skribbliobot calibrate`".into());
+    }
+    settings.check_files()?;
+
+    let image = load_image(matches)?;
+    let fit = budget::fit(&image, settings);
+    if !fit.lowered.is_empty() {
+        println!(
+            "Lowered to {} to fit into the time budget",
+            fit.lowered.join(", ")
+        );
+    }
+
+    draw_session(settings, Session::new(&fit.settings, &fit.image))
+}
+
+fn resume(settings: &Settings) -> Result<(), String> {
+    let session = Session::load()?.ok_or("There is no drawing to continue")?;
+    println!("Continuing after {} steps", session.done);
+
+    draw_session(settings, session)
+}
+
+// draws the image of the session with its settings, the hotkeys come from `settings`
+fn draw_session(settings: &Settings, session: Session) -> Result<(), String> {
+    session.settings.check_files()?;
+    let image = session.image()?;
+    let running = Arc::new(AtomicBool::new(false));
+    let paused = Arc::new(AtomicBool::new(false));
+    let hotkeys = hotkeys::start(settings.hotkeys(), running.clone(), {
+        let running = running.clone();
+        let paused = paused.clone();
+        move |action| hotkeys::control_drawing(action, &running, &paused)
+    });
+
+    let desktop = desktop::open(Some(&session.settings));
+    let mut drawer = Drawer::from_settings(&session.settings)
+        .with_skip(session.done)
+        .with_pause(paused);
+    let (color_x, color_y) = (session.settings.color_x, session.settings.color_y);
+
+    let recorder = Rc::new(Recorder::start(session));
+    let resume = settings.hotkeys().resume;
+    drawer = drawer.with_events({
+        let recorder = recorder.clone();
+
+        move |event| match event {
+            DrawEvent::Paused(reason) => println!("\n{} - Resume with {}", reason, resume),
+            DrawEvent::Resumed => println!("Drawing"),
+            DrawEvent::Progress(progress) => {
+                recorder.record(progress.steps);
+
+                // overwrite the line of the last progress
+                print!("\r{}\x1b[K", progress);
+                io::stdout().flush().unwrap();
+            }
+            DrawEvent::Planned(before, after) => {
+                println!("\rPlanned {} instead of {}\x1b[K", after, before)
+            }
+            DrawEvent::Verified(Ok(0)) => println!("\rNo pixels are missing\x1b[K"),
+            DrawEvent::Verified(Ok(wrong)) => {
+                println!("\rRedrawing {} missing pixels\x1b[K", wrong)
+            }
+            DrawEvent::Verified(Err(err)) => println!("\rCan't verify the drawing: {}\x1b[K", err),
+        }
+    });
+
+    match hotkeys {
+        Ok(_) => println!("Drawing - {}", settings.hotkeys().drawing_help()),
+        Err(err) => println!("Drawing - {}", err),
+    }
+
+    // focus the drawing window before drawing
+    desktop.move_cursor(color_x, color_y);
+    desktop.left_click(ClickType::Once);
+
+    running.store(true, Ordering::Relaxed);
+    let summary = drawer.draw(&*desktop, &image, running.clone());
+    running.store(false, Ordering::Relaxed);
+    recorder.finish(&summary);
+    println!("\n{}", summary);
+
+    if summary.done < summary.total {
+        println!("Continue with `skribbliobot resume`");
+    }
+
+    match drawer.stop_reason() {
+        Some(reason) => Err(format!("Cancelled - {}", reason)),
+        None => Ok(()),
+    }
+}
+
+fn preview(settings: &Settings, matches: &ArgMatches) -> Result<(), String> {
+    if settings.drawing_width == 0 || settings.drawing_height == 0 {
+        return Err("Please enter the canvas size with `skribbliobot calibrate`".into());
+    }
+    settings.check_files()?;
+
+    let image = load_image(matches)?;
+    let converted = image_converter::convert_with_settings(image, settings);
+
+    let output = matches.value_of("output").unwrap();
+    converted
+        .save(output)
+        .map_err(|err| format!("Failed to write {}: {}", output, err))
+}
+
+fn dry_run(settings: &Settings, matches: &ArgMatches) -> Result<(), String> {
+    if !settings.is_ready() {
+        return Err("Please enter positions with `skribbliobot calibrate`".into());
+    }
+    settings.check_files()?;
+
+    let image = load_image(matches)?;
+    let fit = budget::fit(&image, settings);
+
+    let output = matches.value_of("output").unwrap();
+    fit.canvas
+        .save(output)
+        .map_err(|err| format!("Failed to write {}: {}", output, err))?;
+
+    println!("{}", fit);
+    if let Some(restriction) = fit.restriction(&image) {
+        println!("{}", restriction);
+    }
+
+    Ok(())
+}
+
+fn benchmark(settings: &Settings, matches: &ArgMatches) -> Result<(), String> {
+    if !settings.is_ready() {
+        return Err("Please enter positions with `skribbliobot calibrate`".into());
+    }
+    settings.check_files()?;
+
+    let paths = matches
+        .value_of("image")
+        .into_iter()
+        .chain(matches.values_of("images").into_iter().flatten());
+
+    let plain = Settings {
+        optimize: Some(false),
+        ..settings.clone()
+    };
+    let optimized = Settings {
+        optimize: Some(true),
+        ..settings.clone()
+    };
+
+    let mut totals = (Stats::default(), Stats::default());
+    for path in paths {
+        let image = image::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+        let converted = image_converter::convert_with_settings(image, settings);
+
+        let (_, before) = simulator::simulate(&plain, &converted);
+        let (_, after) = simulator::simulate(&optimized, &converted);
+        println!("{}", path);
+        print_comparison(&before, &after);
+
+        totals.0 += before;
+        totals.1 += after;
+    }
+
+    println!("Total");
+    print_comparison(&totals.0, &totals.1);
+
+    Ok(())
+}
+
+fn print_comparison(before: &Stats, after: &Stats) {
+    // 200 -> 150 (-25%)
+    let change = |before: u64, after: u64| {
+        let percent = if before == 0 {
+            0.0
+        } else {
+            (after as f64 - before as f64) / before as f64 * 100.0
+        };
+        format!("{} -> {} ({:+.0}%)", before, after, percent)
+    };
+
+    println!("  travel:         {}", change(before.travel, after.travel));
+    println!(
+        "  color switches: {}",
+        change(before.color_switches.into(), after.color_switches.into())
+    );
+    println!(
+        "  clicks:         {}",
+        change(before.clicks.into(), after.clicks.into())
+    );
+}
+
+fn search(matches: &ArgMatches) -> Result<(), String> {
+    let query = matches.value_of("query").unwrap();
+    let count: usize = parse(matches, "count")?.unwrap();
+    let output = Path::new(matches.value_of("output").unwrap());
+
+    let mut image_downloader = ImageDownloader::new(query).map_err(|err| err.to_string())?;
+
+    // only keep characters that are safe in file names
+    let name: String = query
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+
+    let mut saved = 0;
+    while saved < count {
+        let data = match image_downloader.download_image() {
+            Ok(data) => data,
+            Err(DownloadImageError::Error(err)) => {
+                println!("{}", err);
+                continue;
+            }
+            Err(DownloadImageError::NoImagesLeft) => break,
+        };
+
+        if let Ok(image) = image::load_from_memory(&data) {
+            saved += 1;
+
+            let path = output.join(format!("{}-{}.png", name, saved));
+            image
+                .save(&path)
+                .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+            println!("{}", path.display());
+        }
+    }
+
+    Ok(())
+}
+
+fn calibrate(settings: &mut Settings, matches: &ArgMatches) -> Result<(), String> {
+    // the palette of the game is needed to find it
+    if let Some(profile) = matches.value_of("profile") {
+        Profile::find(profile)?;
+        settings.profile = Some(profile.into());
+    }
+
+    let calibration = if matches.is_present("auto") {
+        let screenshot = desktop::open(Some(settings)).capture_screen()?;
+        Some(calibration::calibrate(&screenshot, &settings.palette()?)?)
+    } else if let Some(path) = matches.value_of("screenshot") {
+        Some(calibration::calibrate_file(path, &settings.palette()?)?)
+    } else {
+        None
+    };
+
+    if let Some(calibration) = &calibration {
+        calibration.apply(settings);
+    }
+
+    if let Some(canvas) = parse_box(matches, "canvas")? {
+        settings.drawing_x = canvas[0];
+        settings.drawing_y = canvas[1];
+        settings.drawing_width = canvas[2];
+        settings.drawing_height = canvas[3];
+    }
+
+    if let Some(palette) = parse_box(matches, "palette")? {
+        settings.color_x = palette[0];
+        settings.color_y = palette[1];
+        settings.color_width = palette[2];
+        settings.color_height = palette[3];
+    }
+
+    if let Some(brush) = parse_box(matches, "brush-tool")? {
+        settings.brush_tool_x = Some(brush[0]);
+        settings.brush_tool_y = Some(brush[1]);
+    }
+
+    if let Some(bucket) = parse_box(matches, "fill-tool")? {
+        settings.fill_tool_x = Some(bucket[0]);
+        settings.fill_tool_y = Some(bucket[1]);
+    }
+
+    if let Some(brushes) = parse_box(matches, "brush")? {
+        settings.brushes = Some(
+            brushes
+                .chunks(3)
+                .map(|brush| Brush {
+                    x: brush[0],
+                    y: brush[1],
+                    size: brush[2].max(1),
+                })
+                .collect(),
+        );
+    }
+
+    let changed = [
+        "profile",
+        "canvas",
+        "palette",
+        "brush-tool",
+        "fill-tool",
+        "brush",
+    ]
+    .iter()
+    .any(|name| matches.is_present(name));
+    if calibration.is_some() || changed {
+        settings
+            .save()
+            .map_err(|err| format!("Failed to write settings: {}", err))?;
+    }
+
+    println!("Game: {}", settings.profile()?.palette.name);
+    println!(
+        "Canvas: {} {} {} {}",
+        settings.drawing_x, settings.drawing_y, settings.drawing_width, settings.drawing_height
+    );
+    println!(
+        "Palette: {} {} {} {}",
+        settings.color_x, settings.color_y, settings.color_width, settings.color_height
+    );
+    if let Some(tools) = settings.tools() {
+        println!(
+            "Tools: brush {} {}, bucket {} {}",
+            tools.brush.0, tools.brush.1, tools.bucket.0, tools.bucket.1
+        );
+    }
+    if let Some(brushes) = settings.brushes() {
+        println!("Brushes: {}", Brush::format_list(&brushes));
+    }
+    if let Some(picker) = settings.color_picker() {
+        println!(
+            "Color picker: button {} {}, entry {} {}",
+            picker.button.0, picker.button.1, picker.entry.0, picker.entry.1
+        );
+    }
+
+    Ok(())
+}
+
+// command line flags take precedence over the saved settings
+fn apply_overrides(settings: &mut Settings, matches: &ArgMatches) -> Result<(), String> {
+    if matches.is_present("dither") {
+        settings.dithering = Some(Dithering::FloydSteinberg);
+    }
+    if matches.is_present("no-dither") {
+        settings.dithering = Some(Dithering::None);
+    }
+    if let Some(dithering) = matches.value_of("dithering") {
+        settings.dithering = Dithering::from_id(dithering);
+    }
+    if let Some(distance) = matches.value_of("color-distance") {
+        settings.color_distance = ColorDistance::from_id(distance);
+    }
+    if let Some(profile) = matches.value_of("profile") {
+        Profile::find(profile)?;
+        settings.profile = Some(profile.into());
+    }
+    if let Some(palette) = matches.value_of("palette-file") {
+        Palette::load(palette)?;
+        settings.palette = Some(palette.into());
+    }
+    if let Some(sketch) = matches.value_of("sketch") {
+        settings.sketch = Sketch::from_id(sketch);
+    }
+    if matches.is_present("grayscale") {
+        settings.grayscale = Some(true);
+    }
+    if matches.is_present("no-grayscale") {
+        settings.grayscale = Some(false);
+    }
+    if matches.is_present("checkerboard") {
+        settings.checkerboard = true;
+    }
+    if matches.is_present("no-checkerboard") {
+        settings.checkerboard = false;
+    }
+    if matches.is_present("fill") {
+        settings.fill = Some(true);
+    }
+    if matches.is_present("no-fill") {
+        settings.fill = Some(false);
+    }
+    if matches.is_present("optimize") {
+        settings.optimize = Some(true);
+    }
+    if matches.is_present("no-optimize") {
+        settings.optimize = Some(false);
+    }
+    if matches.is_present("verify") {
+        settings.verify = Some(true);
+    }
+    if matches.is_present("no-verify") {
+        settings.verify = Some(false);
+    }
+    if let Some(strokes) = matches.value_of("strokes") {
+        settings.strokes = Strokes::from_id(strokes);
+    }
+    if let Some(order) = matches.value_of("order") {
+        settings.order = DrawOrder::from_id(order);
+    }
+    if let Some(pointer_guard) = matches.value_of("pointer-guard") {
+        settings.pointer_guard = PointerGuard::from_id(pointer_guard);
+    }
+    if let Some(scale) = parse(matches, "scale")? {
+        settings.scale = scale;
+    }
+    if let Some(delay) = parse(matches, "delay")? {
+        settings.delay = delay;
+    }
+    if let Some(step) = parse(matches, "step")? {
+        settings.step = Some(step);
+    }
+    if let Some(custom_colors) = parse(matches, "custom-colors")? {
+        settings.custom_colors = Some(custom_colors);
+    }
+    if let Some(max_colors) = parse(matches, "max-colors")? {
+        settings.max_colors = Some(max_colors);
+    }
+    if let Some(time_budget) = parse(matches, "time-budget")? {
+        settings.time_budget = Some(time_budget);
+    }
+
+    Ok(())
+}
+
+fn load_image(matches: &ArgMatches) -> Result<DynamicImage, String> {
+    match matches.value_of("image") {
+        Some(path) => image::open(path).map_err(|err| format!("Failed to open {}: {}", path, err)),
+        None => image_converter::image_from_clipboard(),
+    }
+}
+
+fn parse<T: FromStr>(matches: &ArgMatches, name: &str) -> Result<Option<T>, String> {
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|_| format!("Invalid value for --{}: {}", name, value))
+        })
+        .transpose()
+}
+
+fn parse_box(matches: &ArgMatches, name: &str) -> Result<Option<Vec<u32>>, String> {
+    matches
+        .values_of(name)
+        .map(|values| {
+            values
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("Invalid value for --{}: {}", name, value))
+                })
+                .collect()
+        })
+        .transpose()
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+
+// reference white of sRGB (D65)
+const WHITE_X: f64 = 0.950_47;
+const WHITE_Y: f64 = 1.0;
+const WHITE_Z: f64 = 1.088_83;
+
+/// How the difference between two colors is measured when picking palette colors
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorDistance {
+    /// Sum of the channel differences, fast but far from what the eye sees
+    Rgb,
+    /// Euclidean distance in CIELAB (CIE76)
+    Cielab,
+    /// CIELAB with the CIEDE2000 corrections for hue and saturation
+    Ciede2000,
+    /// Euclidean distance in OKLab
+    Oklab,
+}
+
+impl ColorDistance {
+    pub const ALL: [ColorDistance; 4] = [
+        ColorDistance::Rgb,
+        ColorDistance::Cielab,
+        ColorDistance::Ciede2000,
+        ColorDistance::Oklab,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            ColorDistance::Rgb => "rgb",
+            ColorDistance::Cielab => "cielab",
+            ColorDistance::Ciede2000 => "ciede2000",
+            ColorDistance::Oklab => "oklab",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<ColorDistance> {
+        ColorDistance::ALL
+            .iter()
+            .copied()
+            .find(|distance| distance.id() == id)
+    }
+
+    /// Converts a color into the space the distance is measured in
+    pub fn coordinates(self, rgb: [u8; 3]) -> [f64; 3] {
+        match self {
+            ColorDistance::Rgb => [f64::from(rgb[0]), f64::from(rgb[1]), f64::from(rgb[2])],
+            ColorDistance::Cielab | ColorDistance::Ciede2000 => lab(rgb),
+            ColorDistance::Oklab => oklab(rgb),
+        }
+    }
+
+    /// Distance between two colors returned by `coordinates`
+    pub fn compare(self, a: [f64; 3], b: [f64; 3]) -> f64 {
+        match self {
+            ColorDistance::Rgb => a.iter().zip(b.iter()).map(|(a, b)| (a - b).abs()).sum(),
+            ColorDistance::Cielab | ColorDistance::Oklab => a
+                .iter()
+                .zip(b.iter())
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f64>()
+                .sqrt(),
+            ColorDistance::Ciede2000 => ciede2000(a, b),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn distance(self, a: [u8; 3], b: [u8; 3]) -> f64 {
+        self.compare(self.coordinates(a), self.coordinates(b))
+    }
+}
+
+// sRGB channel to linear light
+fn linear(channel: u8) -> f64 {
+    let channel = f64::from(channel) / 255.0;
+
+    if channel <= 0.040_45 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// CIELAB coordinates of an sRGB color
+pub fn lab(rgb: [u8; 3]) -> [f64; 3] {
+    let (r, g, b) = (linear(rgb[0]), linear(rgb[1]), linear(rgb[2]));
+
+    let x = 0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b;
+    let z = 0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b;
+
+    let f = |t: f64| {
+        let delta: f64 = 6.0 / 29.0;
+
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x / WHITE_X), f(y / WHITE_Y), f(z / WHITE_Z));
+
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// OKLab coordinates of an sRGB color
+pub fn oklab(rgb: [u8; 3]) -> [f64; 3] {
+    let (r, g, b) = (linear(rgb[0]), linear(rgb[1]), linear(rgb[2]));
+
+    let l = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+    let m = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+    let s = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+
+    [
+        0.210_454_255_3 * l + 0.793_617_785 * m - 0.004_072_046_8 * s,
+        1.977_998_495_1 * l - 2.428_592_205 * m + 0.450_593_709_9 * s,
+        0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766 * s,
+    ]
+}
+
+/// CIEDE2000 difference of two CIELAB colors
+pub fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+    let pow7 = |value: f64| value.powi(7);
+
+    let c_mean = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt());
+
+    let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1, c2) = ((a1 * a1 + b1 * b1).sqrt(), (a2 * a2 + b2 * b2).sqrt());
+
+    // hue angle in degrees between 0 and 360
+    let hue = |a: f64, b: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let (h1, h2) = (hue(a1, b1), hue(a2, b2));
+    let colorless = c1 * c2 == 0.0;
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if colorless {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if colorless {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+
+    let cos = |degrees: f64| degrees.to_radians().cos();
+    let t =
+        1.0 - 0.17 * cos(h_mean - 30.0) + 0.24 * cos(2.0 * h_mean) + 0.32 * cos(3.0 * h_mean + 6.0)
+            - 0.20 * cos(4.0 * h_mean - 63.0);
+
+    let delta_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (pow7(c_mean) / (pow7(c_mean) + pow7(25.0))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_mean - 50.0).powi(2) / (20.0 + (l_mean - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_mean;
+    let s_h = 1.0 + 0.015 * c_mean * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+
+    (l * l + c * c + h * h + r_t * c * h).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+        for (actual_channel, expected_channel) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual_channel - expected_channel).abs() < tolerance,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn converts_to_lab() {
+        assert_close(lab([255, 255, 255]), [100.0, 0.0, 0.0], 0.01);
+        assert_close(lab([0, 0, 0]), [0.0, 0.0, 0.0], 0.01);
+        assert_close(lab([255, 0, 0]), [53.24, 80.09, 67.20], 0.01);
+        assert_close(lab([0, 0, 255]), [32.30, 79.19, -107.86], 0.01);
+    }
+
+    #[test]
+    fn converts_to_oklab() {
+        assert_close(oklab([255, 255, 255]), [1.0, 0.0, 0.0], 0.001);
+        assert_close(oklab([255, 0, 0]), [0.628, 0.2249, 0.1258], 0.001);
+        assert_close(oklab([0, 255, 0]), [0.8664, -0.2339, 0.1795], 0.001);
+    }
+
+    // pairs from "The CIEDE2000 Color-Difference Formula" by Sharma, Wu and Dalal
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+            ([50.0, 2.49, -0.001], [50.0, -2.49, 0.0009], 7.1792),
+            ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+            ([50.0, 2.5, 0.0], [50.0, 3.1736, 0.5854], 1.0),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+            (
+                [90.9257, -0.5406, -0.9208],
+                [88.6381, -0.8985, -0.7239],
+                1.5381,
+            ),
+        ];
+
+        for &(lab1, lab2, expected) in pairs.iter() {
+            let difference = ciede2000(lab1, lab2);
+            assert!(
+                (difference - expected).abs() < 0.0001,
+                "{:?} {:?}: {} != {}",
+                lab1,
+                lab2,
+                difference,
+                expected
+            );
+            assert!((ciede2000(lab2, lab1) - expected).abs() < 0.0001);
+        }
+    }
+
+    #[test]
+    fn measures_distances() {
+        let (black, white) = ([0, 0, 0], [255, 255, 255]);
+
+        assert_eq!(ColorDistance::Rgb.distance(black, white), 765.0);
+        assert!((ColorDistance::Cielab.distance(black, white) - 100.0).abs() < 0.01);
+        assert!((ColorDistance::Ciede2000.distance(black, white) - 100.0).abs() < 0.01);
+        assert!((ColorDistance::Oklab.distance(black, white) - 1.0).abs() < 0.001);
+
+        for &distance in ColorDistance::ALL.iter() {
+            assert!(distance.distance([12, 200, 99], [12, 200, 99]).abs() < 0.000_001);
+        }
+    }
+}
@@ -1,10 +1,15 @@
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, fmt};
+
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
 pub struct ColorCoord {
     pub x: u32,
     pub y: u32,
 }
 
-#[derive(PartialEq, Eq, Debug, Hash, Clone)]
+/// A color, written as `#rrggbb` in files
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Hash, Clone)]
+#[serde(try_from = "String", into = "String")]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -12,11 +17,52 @@ pub struct Color {
 }
 
 impl Color {
+    /// Reads `#rrggbb`, the # is optional
+    pub fn parse(text: &str) -> Result<Color, String> {
+        let hex = text.trim().trim_start_matches('#');
+        let channel = |index: usize| {
+            hex.get(index..index + 2)
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(|| format!("Not a color like #ff8000: {}", text))
+        };
+
+        if hex.len() != 6 {
+            return Err(format!("Not a color like #ff8000: {}", text));
+        }
+
+        Ok(Color {
+            r: channel(0)?,
+            g: channel(2)?,
+            b: channel(4)?,
+        })
+    }
+
     pub fn brightness(&self) -> u32 {
         u32::from(self.r) * 212 + u32::from(self.g) * 715 + u32::from(self.b) * 72
     }
 }
 
+// #rrggbb
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Color, String> {
+        Color::parse(&text)
+    }
+}
+
+impl From<Color> for String {
+    fn from(color: Color) -> String {
+        color.to_string()
+    }
+}
+
 pub const WHITE: Color = Color {
     r: 255,
     g: 255,
@@ -115,3 +161,19 @@ pub const DARK_BROWN: Color = Color {
     g: 48,
     b: 13,
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_eq!(Color::parse("#ef130b").unwrap(), LIGHT_RED);
+        assert_eq!(Color::parse("FF7100").unwrap(), LIGHT_ORANGE);
+        assert_eq!(LIGHT_RED.to_string(), "#ef130b");
+
+        assert!(Color::parse("#ef130").is_err());
+        assert!(Color::parse("#ef130g").is_err());
+        assert!(Color::parse("#ef130bb").is_err());
+    }
+}
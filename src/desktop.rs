@@ -1,10 +1,12 @@
-use std::{process, ptr};
-use x11::{xlib, xtest};
-
-pub struct Desktop {
-    display: *mut xlib::_XDisplay,
-    root: xlib::Window,
-}
+use crate::{
+    drawer::Box,
+    image_converter::Image,
+    settings::{self, Settings},
+    uinput_desktop::UinputDesktop,
+};
+use image::{ImageBuffer, Rgb};
+use std::{mem, os::raw::c_ulong, process, ptr, sync::Arc, thread, time::Duration};
+use x11::{keysym, xlib, xtest};
 
 #[derive(PartialEq)]
 pub enum ClickType {
@@ -13,49 +15,288 @@ pub enum ClickType {
     Up,
 }
 
-impl Desktop {
-    pub fn new() -> Self {
+/// Everything the drawer needs to control the mouse and keyboard
+pub trait Desktop: Send + Sync {
+    fn move_cursor(&self, x: u32, y: u32);
+
+    fn press(&self);
+
+    fn release(&self);
+
+    /// Types a single character, `'\n'` presses Return
+    fn type_key(&self, key: char);
+
+    /// Waits between two actions so the game can keep up
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+
+    /// Where the mouse really is, None if it can't be queried
+    fn pointer_position(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    /// Screenshot of the whole screen
+    fn capture_screen(&self) -> Result<Image, String> {
+        Err("Screen capture needs an X display".into())
+    }
+
+    /// Screenshot of a part of the screen, like the canvas,
+    /// smaller than the area if it sticks out of the screen
+    fn capture_area(&self, _area: Box) -> Result<Image, String> {
+        Err("Screen capture needs an X display".into())
+    }
+
+    fn left_click(&self, click_type: ClickType) {
+        if click_type == ClickType::Down || click_type == ClickType::Once {
+            self.press();
+        }
+        if click_type == ClickType::Up || click_type == ClickType::Once {
+            self.release();
+        }
+    }
+}
+
+// XTest if there's an X display, uinput otherwise
+pub fn open(settings: Option<&Settings>) -> Arc<dyn Desktop> {
+    if let Some(desktop) = XTestDesktop::new() {
+        return Arc::new(desktop);
+    }
+
+    let screen_size = settings.and_then(|settings| {
+        settings
+            .screen_width
+            .and_then(|width| settings.screen_height.map(|height| (width, height)))
+    });
+
+    match screen_size {
+        Some((width, height)) => match UinputDesktop::new(width, height) {
+            Ok(desktop) => Arc::new(desktop),
+            Err(err) => {
+                println!("Could not open Display or uinput: {}", err);
+                process::exit(1);
+            }
+        },
+        None => {
+            println!(
+                "Could not open Display, set screen_width and screen_height in {} to use uinput",
+                settings::FILENAME
+            );
+            process::exit(1);
+        }
+    }
+}
+
+pub struct XTestDesktop {
+    display: *mut xlib::_XDisplay,
+    root: xlib::Window,
+}
+
+impl XTestDesktop {
+    /// Returns None if no X display is available
+    pub fn new() -> Option<Self> {
         unsafe {
             let display = xlib::XOpenDisplay(ptr::null());
 
             if display.is_null() {
-                println!("Could not open Display");
-                process::exit(1);
+                return None;
             }
 
             let root = xlib::XDefaultRootWindow(display);
 
-            Desktop { display, root }
+            Some(XTestDesktop { display, root })
+        }
+    }
+
+    // size of the root window
+    fn screen_size(&self) -> (u32, u32) {
+        unsafe {
+            let mut attributes: xlib::XWindowAttributes = mem::zeroed();
+            xlib::XGetWindowAttributes(self.display, self.root, &mut attributes);
+
+            (attributes.width as u32, attributes.height as u32)
         }
     }
 
-    pub fn move_cursor(&self, x: u32, y: u32) {
+    // whether the key that types the keysym has to be pressed with shift, like `#` on `3`
+    fn needs_shift(&self, keysym: u32) -> bool {
+        let levels = unsafe {
+            let keycode = xlib::XKeysymToKeycode(self.display, keysym.into());
+            // display, keycode, group, level
+            [0, 1].map(|level| xlib::XkbKeycodeToKeysym(self.display, keycode, 0, level))
+        };
+
+        shifted(keysym, levels)
+    }
+
+    fn fake_key(&self, keysym: u32, is_press: bool) {
+        unsafe {
+            let keycode = xlib::XKeysymToKeycode(self.display, keysym.into());
+            // display, keycode, is_press, delay
+            xtest::XTestFakeKeyEvent(
+                self.display,
+                keycode.into(),
+                is_press as _,
+                xlib::CurrentTime,
+            );
+        }
+    }
+}
+
+impl Desktop for XTestDesktop {
+    fn move_cursor(&self, x: u32, y: u32) {
         unsafe {
             xlib::XWarpPointer(self.display, 0, self.root, 0, 0, 0, 0, x as _, y as _);
             xlib::XFlush(self.display);
         }
     }
 
-    pub fn left_click(&self, click_type: ClickType) {
+    fn press(&self) {
         unsafe {
             // display, button, is_press, delay
-            if click_type == ClickType::Down || click_type == ClickType::Once {
-                xtest::XTestFakeButtonEvent(self.display, 1, 1, xlib::CurrentTime);
+            xtest::XTestFakeButtonEvent(self.display, 1, 1, xlib::CurrentTime);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    fn release(&self) {
+        unsafe {
+            xtest::XTestFakeButtonEvent(self.display, 1, 0, xlib::CurrentTime);
+            xlib::XFlush(self.display);
+        }
+    }
+
+    fn pointer_position(&self) -> Option<(u32, u32)> {
+        unsafe {
+            let (mut root, mut child) = (0, 0);
+            let (mut root_x, mut root_y, mut window_x, mut window_y) = (0, 0, 0, 0);
+            let mut mask = 0;
+
+            let on_screen = xlib::XQueryPointer(
+                self.display,
+                self.root,
+                &mut root,
+                &mut child,
+                &mut root_x,
+                &mut root_y,
+                &mut window_x,
+                &mut window_y,
+                &mut mask,
+            );
+
+            if on_screen == xlib::True {
+                Some((root_x.max(0) as u32, root_y.max(0) as u32))
+            } else {
+                None
             }
-            if click_type == ClickType::Up || click_type == ClickType::Once {
-                xtest::XTestFakeButtonEvent(self.display, 1, 0, xlib::CurrentTime);
+        }
+    }
+
+    fn capture_screen(&self) -> Result<Image, String> {
+        let (width, height) = self.screen_size();
+
+        self.capture_area(Box {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    fn capture_area(&self, area: Box) -> Result<Image, String> {
+        // XGetImage fails if the area sticks out of the screen
+        let (screen_width, screen_height) = self.screen_size();
+        let width = area.width.min(screen_width.saturating_sub(area.x));
+        let height = area.height.min(screen_height.saturating_sub(area.y));
+        if width == 0 || height == 0 {
+            return Err("The area is outside of the screen".into());
+        }
+
+        unsafe {
+            let ximage = xlib::XGetImage(
+                self.display,
+                self.root,
+                area.x as _,
+                area.y as _,
+                width,
+                height,
+                xlib::XAllPlanes(),
+                xlib::ZPixmap,
+            );
+
+            if ximage.is_null() {
+                return Err("Failed to capture the screen".into());
             }
 
+            let masks = [
+                (*ximage).red_mask,
+                (*ximage).green_mask,
+                (*ximage).blue_mask,
+            ];
+            let image = ImageBuffer::from_fn(width, height, |x, y| {
+                let pixel = xlib::XGetPixel(ximage, x as _, y as _);
+
+                Rgb([
+                    channel(pixel, masks[0]),
+                    channel(pixel, masks[1]),
+                    channel(pixel, masks[2]),
+                ])
+            });
+
+            xlib::XDestroyImage(ximage);
+
+            Ok(image)
+        }
+    }
+
+    fn type_key(&self, key: char) {
+        // latin-1 characters share their keysym with their code point
+        let keysym = match key {
+            '\n' => keysym::XK_Return,
+            key if key.is_ascii_graphic() || key == ' ' => key as u32,
+            _ => return,
+        };
+
+        let shift = self.needs_shift(keysym);
+        if shift {
+            self.fake_key(keysym::XK_Shift_L, true);
+        }
+
+        self.fake_key(keysym, true);
+        self.fake_key(keysym, false);
+
+        if shift {
+            self.fake_key(keysym::XK_Shift_L, false);
+        }
+
+        unsafe {
             xlib::XFlush(self.display);
         }
     }
 }
 
+// true if the keysym is on the shift level of its key, the symbols of the key without and with shift
+fn shifted(keysym: u32, levels: [c_ulong; 2]) -> bool {
+    let keysym = c_ulong::from(keysym);
+    levels[0] != keysym && levels[1] == keysym
+}
+
+// 0-255 value of the channel in the mask, 16 bit visuals only have 5 or 6 bits per channel
+fn channel(pixel: c_ulong, mask: c_ulong) -> u8 {
+    let shift = mask.trailing_zeros();
+    let max = mask >> shift;
+    if max == 0 {
+        return 0;
+    }
+
+    (((pixel & mask) >> shift) * 255 / max) as u8
+}
+
 // Probably fine, no 2 draw processes are running concurrently
-unsafe impl Send for Desktop {}
-unsafe impl Sync for Desktop {}
+unsafe impl Send for XTestDesktop {}
+unsafe impl Sync for XTestDesktop {}
 
-impl Drop for Desktop {
+impl Drop for XTestDesktop {
     fn drop(&mut self) {
         unsafe {
             xlib::XFlush(self.display);
@@ -63,3 +304,121 @@ impl Drop for Desktop {
         }
     }
 }
+
+#[cfg(test)]
+pub use recording::{InputEvent, RecordingDesktop};
+
+#[cfg(test)]
+mod recording {
+    use super::Desktop;
+    use std::sync::Mutex;
+
+    #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+    pub enum InputEvent {
+        Move(u32, u32),
+        Press,
+        Release,
+        Key(char),
+    }
+
+    /// Keeps every event in memory instead of sending it anywhere
+    #[derive(Default)]
+    pub struct RecordingDesktop {
+        events: Mutex<Vec<InputEvent>>,
+    }
+
+    impl RecordingDesktop {
+        pub fn new() -> Self {
+            RecordingDesktop::default()
+        }
+
+        pub fn events(&self) -> Vec<InputEvent> {
+            self.events.lock().unwrap().clone()
+        }
+
+        fn record(&self, event: InputEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    impl Desktop for RecordingDesktop {
+        fn move_cursor(&self, x: u32, y: u32) {
+            self.record(InputEvent::Move(x, y));
+        }
+
+        fn press(&self) {
+            self.record(InputEvent::Press);
+        }
+
+        fn release(&self) {
+            self.record(InputEvent::Release);
+        }
+
+        fn type_key(&self, key: char) {
+            self.record(InputEvent::Key(key));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presses_shift_for_shifted_symbols() {
+        // the symbols of the keys on a US layout
+        let key = |unshifted: u32, with_shift: u32| [unshifted.into(), with_shift.into()];
+
+        assert!(shifted(
+            '#' as u32,
+            key(keysym::XK_3, keysym::XK_numbersign)
+        ));
+        assert!(shifted(
+            '_' as u32,
+            key(keysym::XK_minus, keysym::XK_underscore)
+        ));
+        assert!(shifted('A' as u32, key(keysym::XK_a, keysym::XK_A)));
+        assert!(!shifted('a' as u32, key(keysym::XK_a, keysym::XK_A)));
+        assert!(!shifted(
+            '3' as u32,
+            key(keysym::XK_3, keysym::XK_numbersign)
+        ));
+        // keys without a shift level
+        assert!(!shifted(keysym::XK_Return, key(keysym::XK_Return, 0)));
+    }
+
+    #[test]
+    fn reads_channels_of_any_depth() {
+        // 24 bit
+        assert_eq!(channel(0x12_34_56, 0xff_00_00), 0x12);
+        assert_eq!(channel(0x12_34_56, 0x00_00_ff), 0x56);
+        // 16 bit with 5 bits of red, 6 of green and 5 of blue
+        let white = 0xffff;
+        assert_eq!(channel(white, 0xf800), 255);
+        assert_eq!(channel(white, 0x07e0), 255);
+        // only the top bit of red
+        assert_eq!(channel(0x8000, 0xf800), 131);
+        assert_eq!(channel(0, 0x001f), 0);
+    }
+
+    #[test]
+    fn left_click_presses_and_releases() {
+        let desktop = RecordingDesktop::new();
+
+        desktop.move_cursor(4, 2);
+        desktop.left_click(ClickType::Once);
+        desktop.left_click(ClickType::Down);
+        desktop.left_click(ClickType::Up);
+
+        assert_eq!(
+            desktop.events(),
+            vec![
+                InputEvent::Move(4, 2),
+                InputEvent::Press,
+                InputEvent::Release,
+                InputEvent::Press,
+                InputEvent::Release,
+            ]
+        );
+    }
+}
@@ -1,19 +1,30 @@
 use crate::{
-    colors::{Color, ColorCoord, *},
+    brushes::{self, Brush, CoarseStroke},
+    colors::{Color, ColorCoord, WHITE},
     desktop::{ClickType, Desktop},
-    image_converter::Image,
+    edges::{self, EdgeMap},
+    fill::{self, FillPlan},
+    image_converter::{Image, Sketch},
+    palette::Palette,
+    planner::{self, Cost, Stroke},
+    settings::Settings,
+    verify,
 };
 use image::Pixel;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt,
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct Box {
     pub x: u32,
     pub y: u32,
@@ -21,57 +32,283 @@ pub struct Box {
     pub height: u32,
 }
 
-fn calculate_color_positions(color_box: Box) -> HashMap<Color, ColorCoord> {
-    let mut map = HashMap::new();
-
-    let xmiddle = color_box.x + (color_box.width / 2);
-    let ymiddle = color_box.y + (color_box.height / 2);
-
-    for x in 0..11 {
-        for y in 0..2 {
-            let xpos = xmiddle + (color_box.width * x);
-            let ypos = ymiddle + (color_box.height * y);
-
-            let color = match (x, y) {
-                (0, 0) => WHITE,
-                (1, 0) => LIGHT_GREY,
-                (2, 0) => LIGHT_RED,
-                (3, 0) => LIGHT_ORANGE,
-                (4, 0) => LIGHT_YELLOW,
-                (5, 0) => LIGHT_GREEN,
-                (6, 0) => LIGHT_CYAN,
-                (7, 0) => LIGHT_BLUE,
-                (8, 0) => LIGHT_MAGENTA,
-                (9, 0) => LIGHT_PINK,
-                (10, 0) => LIGHT_BROWN,
-                (0, 1) => BLACK,
-                (1, 1) => DARK_GREY,
-                (2, 1) => DARK_RED,
-                (3, 1) => DARK_ORANGE,
-                (4, 1) => DARK_YELLOW,
-                (5, 1) => DARK_GREEN,
-                (6, 1) => DARK_CYAN,
-                (7, 1) => DARK_BLUE,
-                (8, 1) => DARK_MAGENTA,
-                (9, 1) => DARK_PINK,
-                (10, 1) => DARK_BROWN,
-                (_, _) => unreachable!(),
-            };
+/// How consecutive pixels of the same color get merged into mouse drags.
+/// The checkerboard never draws two neighbours in the same pass, so with it every pixel is
+/// clicked on its own whichever one is picked
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Strokes {
+    /// Click every pixel on its own
+    Pixels,
+    /// Drag along horizontal runs
+    Rows,
+    /// Drag along vertical runs
+    Columns,
+}
+
+impl Strokes {
+    pub const ALL: [Strokes; 3] = [Strokes::Pixels, Strokes::Rows, Strokes::Columns];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Strokes::Pixels => "pixels",
+            Strokes::Rows => "rows",
+            Strokes::Columns => "columns",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Strokes> {
+        Strokes::ALL
+            .iter()
+            .copied()
+            .find(|strokes| strokes.id() == id)
+    }
+}
+
+/// In which order the strokes of a drawing are drawn
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum DrawOrder {
+    /// Darkest colors first, with as few color switches as possible
+    Brightness,
+    /// Edges and dark strokes first, then mid-tones and highlights last,
+    /// so the picture is recognisable early
+    Importance,
+}
+
+impl DrawOrder {
+    pub const ALL: [DrawOrder; 2] = [DrawOrder::Brightness, DrawOrder::Importance];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            DrawOrder::Brightness => "brightness",
+            DrawOrder::Importance => "importance",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<DrawOrder> {
+        DrawOrder::ALL
+            .iter()
+            .copied()
+            .find(|order| order.id() == id)
+    }
+}
+
+/// What happens when someone else moves the mouse while drawing
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PointerGuard {
+    Off,
+    Pause,
+    Abort,
+}
+
+impl PointerGuard {
+    pub const ALL: [PointerGuard; 3] =
+        [PointerGuard::Off, PointerGuard::Pause, PointerGuard::Abort];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            PointerGuard::Off => "off",
+            PointerGuard::Pause => "pause",
+            PointerGuard::Abort => "abort",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<PointerGuard> {
+        PointerGuard::ALL
+            .iter()
+            .copied()
+            .find(|guard| guard.id() == id)
+    }
+}
+
+/// Sent while drawing
+#[derive(Clone, PartialEq, Debug)]
+pub enum DrawEvent {
+    /// The drawing waits for the resume hotkey, with the reason
+    Paused(String),
+    Resumed,
+    Progress(Progress),
+    /// Travel and color switches of the next strokes before and after ordering them
+    Planned(Cost, Cost),
+    /// Pixels that were missing on the canvas and get drawn again,
+    /// or why the canvas couldn't be captured
+    Verified(Result<usize, String>),
+}
 
-            map.insert(color, ColorCoord { x: xpos, y: ypos });
+// 78s -> 1m 18s
+fn minutes(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}m {}s", seconds / 60, seconds % 60)
+}
+
+/// How far a drawing is
+#[derive(Clone, PartialEq, Debug)]
+pub struct Progress {
+    /// Pixels that are drawn, white pixels don't count
+    pub done: u32,
+    pub total: u32,
+    /// Color of the last stroke
+    pub color: Color,
+    pub elapsed: Duration,
+    /// Strokes and bucket clicks that are done, a resumed drawing skips them
+    pub steps: usize,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            1.0
+        } else {
+            f64::from(self.done) / f64::from(self.total)
         }
     }
 
-    map
+    /// Time until the drawing is finished at the speed so far, None before the first stroke
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.done == 0 {
+            None
+        } else {
+            let left = f64::from(self.total - self.done) / f64::from(self.done);
+            Some(self.elapsed.mul_f64(left))
+        }
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} pixels in {}, drawing {}",
+            self.done,
+            self.total,
+            minutes(self.elapsed),
+            self.color
+        )?;
+
+        if let Some(remaining) = self.remaining() {
+            write!(f, ", about {} left", minutes(remaining))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What a finished or cancelled drawing took
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Summary {
+    pub done: u32,
+    pub total: u32,
+    pub elapsed: Duration,
+    /// Presses on the canvas
+    pub clicks: u32,
+    pub color_switches: u32,
+    pub steps: usize,
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Drew {} of {} pixels in {} with {} clicks and {} color switches",
+            self.done,
+            self.total,
+            minutes(self.elapsed),
+            self.clicks,
+            self.color_switches
+        )
+    }
+}
+
+// counted while drawing
+struct Tally {
+    started: Instant,
+    last_report: Instant,
+    done: u32,
+    total: u32,
+    clicks: u32,
+    color_switches: u32,
+    steps: usize,
+}
+
+impl Tally {
+    fn new(total: u32) -> Tally {
+        Tally {
+            started: Instant::now(),
+            last_report: Instant::now(),
+            done: 0,
+            total,
+            clicks: 0,
+            color_switches: 0,
+            steps: 0,
+        }
+    }
+}
+
+// strokes that change the brightness by more than this per pixel are part of an edge
+const EDGE_THRESHOLD: f32 = 16.0;
+// how often a paused drawer checks whether it can continue
+const PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(50);
+// progress is sent at most this often, and once more when the drawing is done
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+// screen pixels the mouse may be away from where the drawer put it
+const POINTER_TOLERANCE: u32 = 2;
+// rounds of capturing the canvas and drawing the pixels that are missing
+const MAX_REPAIR_PASSES: usize = 2;
+// time for the game to show the last strokes before the canvas is captured
+const VERIFY_DELAY: Duration = Duration::from_millis(200);
+
+/// Screen positions of the tool buttons next to the color palette
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Tools {
+    pub brush: (u32, u32),
+    pub bucket: (u32, u32),
+}
+
+/// Screen positions of the color picker of games that take any color
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct ColorPicker {
+    /// Opens the picker
+    pub button: (u32, u32),
+    /// Takes the color as `#rrggbb`
+    pub entry: (u32, u32),
 }
 
 pub struct Drawer {
     drawing_area: Box,
     checkerboard: bool,
+    strokes: Strokes,
+    order: DrawOrder,
     delay: u64,
     step: f64,
+    // the first swatch of the palette
+    color_box: Box,
     color_coords: HashMap<Color, ColorCoord>,
-    last_color: ColorCoord,
+    // colors are typed into it instead of clicking their swatch if set
+    picker: Option<ColorPicker>,
+    // None after a pause, the color might have been changed meanwhile
+    last_color: Option<Color>,
+    // large regions get filled with the bucket if set
+    fill: Option<Tools>,
+    // sorted from the smallest to the largest
+    brushes: Vec<Brush>,
+    last_brush: Option<Brush>,
+    // the details are traced into lines instead of merged into rows or columns
+    lines: bool,
+    // colors are never mixed and their strokes are ordered to keep the mouse paths short
+    optimize: bool,
+    // the canvas is captured after drawing and missing pixels are drawn again
+    verify: bool,
+    paused: Arc<AtomicBool>,
+    pointer_guard: PointerGuard,
+    // where the drawer put the mouse, None if it doesn't know
+    last_cursor: Option<(u32, u32)>,
+    stop_reason: Option<String>,
+    // steps that were done before the drawing was resumed
+    skip: usize,
+    events: Option<std::boxed::Box<dyn Fn(DrawEvent)>>,
+    tally: Tally,
 }
 
 impl Drawer {
@@ -79,133 +316,1661 @@ impl Drawer {
         drawing_area: Box,
         color_box: Box,
         checkerboard: bool,
+        strokes: Strokes,
         delay: u64,
         step: f64,
     ) -> Drawer {
         Drawer {
             drawing_area,
             checkerboard,
+            strokes,
+            order: DrawOrder::Brightness,
             delay,
             step,
-            color_coords: calculate_color_positions(color_box),
-            last_color: ColorCoord { x: 0, y: 0 },
+            color_box,
+            color_coords: Palette::skribbl().positions(color_box),
+            picker: None,
+            last_color: None,
+            fill: None,
+            brushes: Vec::new(),
+            last_brush: None,
+            lines: false,
+            optimize: false,
+            verify: false,
+            paused: Arc::new(AtomicBool::new(false)),
+            pointer_guard: PointerGuard::Off,
+            last_cursor: None,
+            stop_reason: None,
+            skip: 0,
+            events: None,
+            tally: Tally::new(0),
         }
     }
 
-    pub fn draw(&mut self, desktop: &Desktop, image: &Image, drawer_running: Arc<AtomicBool>) {
+    /// Picks the colors from another palette than skribbl.io's
+    pub fn with_palette(mut self, palette: &Palette) -> Drawer {
+        self.color_coords = palette.positions(self.color_box);
+        self
+    }
+
+    /// Types every color into the color picker instead of clicking a swatch
+    pub fn with_color_picker(mut self, picker: ColorPicker) -> Drawer {
+        self.picker = Some(picker);
+        self
+    }
+
+    pub fn with_order(mut self, order: DrawOrder) -> Drawer {
+        self.order = order;
+        self
+    }
+
+    /// Fills large regions with the bucket instead of drawing every pixel
+    pub fn with_fill(mut self, tools: Tools) -> Drawer {
+        self.fill = Some(tools);
+        self
+    }
+
+    /// Paints broad regions with the larger brushes before drawing the details
+    /// with the smallest one, which should be as large as a pixel
+    pub fn with_brushes(mut self, mut brushes: Vec<Brush>) -> Drawer {
+        brushes.sort_by_key(|brush| brush.size);
+        self.brushes = brushes;
+        self
+    }
+
+    /// Draws the details as continuous drags along their lines, for sketched images
+    pub fn with_lines(mut self) -> Drawer {
+        self.lines = true;
+        self
+    }
+
+    /// Groups the strokes by color and orders them by distance
+    pub fn with_optimize(mut self) -> Drawer {
+        self.optimize = true;
+        self
+    }
+
+    /// Compares the canvas with the image after drawing and repairs dropped clicks
+    pub fn with_verify(mut self) -> Drawer {
+        self.verify = true;
+        self
+    }
+
+    /// Waits before the next stroke while the flag is set
+    pub fn with_pause(mut self, paused: Arc<AtomicBool>) -> Drawer {
+        self.paused = paused;
+        self
+    }
+
+    /// Pauses or cancels the drawing when the mouse is moved by someone else
+    pub fn with_pointer_guard(mut self, pointer_guard: PointerGuard) -> Drawer {
+        self.pointer_guard = pointer_guard;
+        self
+    }
+
+    /// Continues a drawing that was cancelled after the given steps
+    pub fn with_skip(mut self, steps: usize) -> Drawer {
+        self.skip = steps;
+        self
+    }
+
+    pub fn with_events<F>(mut self, events: F) -> Drawer
+    where
+        F: Fn(DrawEvent) + 'static,
+    {
+        self.events = Some(std::boxed::Box::new(events));
+        self
+    }
+
+    /// Why the last drawing was cancelled, None if it finished or was cancelled with the hotkey
+    pub fn stop_reason(&self) -> Option<&str> {
+        self.stop_reason.as_deref()
+    }
+
+    pub fn from_settings(settings: &Settings) -> Drawer {
+        let mut drawer = Drawer::new(
+            settings.drawing_area(),
+            settings.color_box(),
+            settings.checkerboard,
+            settings
+                .strokes
+                .unwrap_or_else(|| Settings::default().strokes.unwrap()),
+            settings.delay as u64,
+            settings.pixel_size(),
+        )
+        .with_palette(&settings.palette().unwrap_or_default());
+        if let Some(brushes) = settings.brushes() {
+            drawer = drawer.with_brushes(brushes);
+        }
+        if let Some(picker) = settings.color_picker() {
+            drawer = drawer.with_color_picker(picker);
+        }
+        if let Some(order) = settings.order {
+            drawer = drawer.with_order(order);
+        }
+        if settings.sketch() != Sketch::Off {
+            drawer = drawer.with_lines();
+        }
+        if settings
+            .optimize
+            .unwrap_or_else(|| Settings::default().optimize.unwrap())
+        {
+            drawer = drawer.with_optimize();
+        }
+        if settings
+            .verify
+            .unwrap_or_else(|| Settings::default().verify.unwrap())
+        {
+            drawer = drawer.with_verify();
+        }
+        drawer = drawer.with_pointer_guard(settings.pointer_guard());
+
+        match settings.tools() {
+            Some(tools) if settings.fill.unwrap_or(false) => drawer.with_fill(tools),
+            _ => drawer,
+        }
+    }
+
+    /// Draws the image, stops early if `drawer_running` is unset
+    pub fn draw(
+        &mut self,
+        desktop: &dyn Desktop,
+        image: &Image,
+        drawer_running: Arc<AtomicBool>,
+    ) -> Summary {
+        self.stop_reason = None;
+        self.last_cursor = None;
+        self.tally = Tally::new(
+            image
+                .enumerate_pixels()
+                .filter(|&(x, y, _)| pixel_color(image, x, y) != WHITE)
+                .count() as u32,
+        );
+
         let mut draw_queue = DrawQueue::new();
+        let edges = match self.order {
+            DrawOrder::Brightness => None,
+            DrawOrder::Importance => Some(edges::sobel(image)),
+        };
+        let edges = edges.as_ref();
 
-        if self.checkerboard {
-            for y in 0..image.height() {
-                let start = 1 - (y % 2);
-                for x in (start..image.width()).step_by(2) {
-                    let pixel = image.get_pixel(x, y).to_rgb();
-                    let color = Color {
-                        r: pixel[0],
-                        g: pixel[1],
-                        b: pixel[2],
-                    };
+        // outlines are detail work as well
+        if let Some(&smallest) = self.brushes.first() {
+            self.select_brush(desktop, smallest);
+        }
+
+        let plan = self.fill.map(|_| fill::plan(image, fill::MIN_REGION_SIZE));
+        if let Some(plan) = &plan {
+            // horizontal parts of the outline as rows, the rest as columns
+            let in_row = |x: u32, y: u32| {
+                let same = |other_x: u32| {
+                    other_x < image.width()
+                        && plan.is_outline(other_x, y)
+                        && image.get_pixel(other_x, y) == image.get_pixel(x, y)
+                };
+
+                same(x.wrapping_sub(1)) || same(x + 1)
+            };
+            self.queue_pixels(&mut draw_queue, image, Strokes::Rows, |x, y| {
+                plan.is_outline(x, y) && in_row(x, y)
+            });
+            self.queue_pixels(&mut draw_queue, image, Strokes::Columns, |x, y| {
+                plan.is_outline(x, y) && !in_row(x, y)
+            });
+            draw_queue.draw(desktop, self, edges, drawer_running.clone());
+
+            self.fill_regions(desktop, plan, image, drawer_running.clone());
+        }
+        let mut covered = vec![false; (image.width() * image.height()) as usize];
+
+        // coarse passes from the largest brush down, each one leaves the edges to the next
+        for index in (1..self.brushes.len()).rev() {
+            let brush = self.brushes[index];
+            let area = (self.drawing_area.width, self.drawing_area.height);
+            let pending = |x: u32, y: u32| {
+                !covered[(y * image.width() + x) as usize]
+                    && plan.as_ref().is_none_or(|plan| !plan.is_filled(x, y))
+            };
+            let coarse = brushes::plan_coarse(image, brush.size, self.step, area, pending);
+
+            // long rows are often cheaper than a drag that leaves their ends behind,
+            // switching to the brush and back costs two more clicks
+            let before = self.count_strokes(image, pending);
+            let after = self.count_strokes(image, |x, y| pending(x, y) && !coarse.is_covered(x, y));
+            if after + coarse.strokes.len() + 2 >= before {
+                continue;
+            }
 
-                    draw_queue.push(DrawInfo { x, y, color });
+            let mut pixels = 0;
+            for (x, y, _) in image.enumerate_pixels() {
+                if coarse.is_covered(x, y) {
+                    covered[(y * image.width() + x) as usize] = true;
+                    pixels += 1;
                 }
             }
 
-            draw_queue.draw(desktop, self, drawer_running.clone());
+            self.select_brush(desktop, brush);
+            self.draw_coarse(desktop, coarse.strokes, pixels, drawer_running.clone());
+        }
 
-            for y in 0..image.height() {
-                let start = y % 2;
-                for x in (start..image.width()).step_by(2) {
-                    let pixel = image.get_pixel(x, y).to_rgb();
-                    let color = Color {
-                        r: pixel[0],
-                        g: pixel[1],
-                        b: pixel[2],
-                    };
+        if let Some(&smallest) = self.brushes.first() {
+            self.select_brush(desktop, smallest);
+        }
+
+        let pending = |x: u32, y: u32| {
+            !covered[(y * image.width() + x) as usize]
+                && plan.as_ref().is_none_or(|plan| !plan.is_filled(x, y))
+        };
+
+        if self.lines {
+            let lines = edges::trace(image, |x, y| {
+                pending(x, y) && pixel_color(image, x, y) != WHITE
+            });
+
+            for line in lines {
+                let (x, y) = line[0];
+                let (end_x, end_y) = line[line.len() - 1];
 
-                    draw_queue.push(DrawInfo { x, y, color });
+                draw_queue.push(DrawInfo {
+                    x,
+                    y,
+                    end_x,
+                    end_y,
+                    path: line.get(1..line.len() - 1).unwrap_or_default().to_vec(),
+                    color: pixel_color(image, x, y),
+                });
+            }
+        } else if self.checkerboard {
+            self.queue_pixels(&mut draw_queue, image, self.strokes, |x, y| {
+                (x + y) % 2 == 1 && pending(x, y)
+            });
+            draw_queue.next_pass();
+
+            self.queue_pixels(&mut draw_queue, image, self.strokes, |x, y| {
+                (x + y) % 2 == 0 && pending(x, y)
+            });
+        } else {
+            self.queue_pixels(&mut draw_queue, image, self.strokes, pending);
+        }
+        draw_queue.draw(desktop, self, edges, drawer_running.clone());
+
+        if self.verify {
+            self.repair(desktop, image, drawer_running);
+        }
+
+        Summary {
+            done: self.tally.done,
+            total: self.tally.total,
+            elapsed: self.tally.started.elapsed(),
+            clicks: self.tally.clicks,
+            color_switches: self.tally.color_switches,
+            steps: self.tally.steps,
+        }
+    }
+
+    // captures the canvas and draws the pixels that don't have their color again,
+    // clicks get lost when the browser can't keep up
+    fn repair(&mut self, desktop: &dyn Desktop, image: &Image, drawer_running: Arc<AtomicBool>) {
+        // the canvas shows what was drawn before resuming, the repair is never skipped
+        self.skip = self.skip.min(self.tally.steps);
+
+        for _ in 0..MAX_REPAIR_PASSES {
+            if !self.keep_running(desktop, &drawer_running) {
+                return;
+            }
+            desktop.sleep(VERIFY_DELAY);
+
+            let capture = match desktop.capture_area(self.drawing_area) {
+                Ok(capture) => capture,
+                Err(err) => {
+                    self.report(DrawEvent::Verified(Err(err)));
+                    return;
+                }
+            };
+            let wrong: HashSet<(u32, u32)> = verify::wrong_pixels(image, &capture, |x, y| {
+                self.screen_position(x, y)
+                    .map(|(x, y)| (x - self.drawing_area.x, y - self.drawing_area.y))
+            })
+            .into_iter()
+            .collect();
+
+            self.report(DrawEvent::Verified(Ok(wrong.len())));
+            if wrong.is_empty() {
+                return;
+            }
+
+            if let Some(&smallest) = self.brushes.first() {
+                self.select_brush(desktop, smallest);
+            }
+            let mut draw_queue = DrawQueue::new();
+            self.queue_pixels(&mut draw_queue, image, self.strokes, |x, y| {
+                wrong.contains(&(x, y))
+            });
+            draw_queue.draw(desktop, self, None, drawer_running.clone());
+        }
+    }
+
+    // counts the drawn pixels and sends the progress now and then, called after every step
+    fn advance(&mut self, pixels: u32, color: &Color) {
+        let tally = &mut self.tally;
+        tally.done = (tally.done + pixels).min(tally.total);
+        tally.steps += 1;
+
+        if tally.last_report.elapsed() >= PROGRESS_INTERVAL || tally.done == tally.total {
+            tally.last_report = Instant::now();
+
+            let progress = Progress {
+                done: tally.done,
+                total: tally.total,
+                color: color.clone(),
+                elapsed: tally.started.elapsed(),
+                steps: tally.steps,
+            };
+            self.report(DrawEvent::Progress(progress));
+        }
+    }
+
+    // strokes with a lower rank are drawn first, the pass of the queue comes after
+    // the importance so edges of every pass are drawn before the rest.
+    // Colors with the same brightness are only kept apart when optimizing.
+    fn rank(&self, pass: u32, info: &DrawInfo, edges: Option<&EdgeMap>) -> Rank {
+        let brightness = info.color.brightness();
+
+        let tier = match edges {
+            None => 0,
+            Some(edges) => {
+                let pixels = info.pixels();
+                let magnitude = pixels
+                    .iter()
+                    .map(|&(x, y)| edges.magnitude(x, y))
+                    .sum::<f32>()
+                    / pixels.len() as f32;
+
+                if magnitude >= EDGE_THRESHOLD || brightness < WHITE.brightness() / 3 {
+                    0
+                } else if brightness < WHITE.brightness() * 2 / 3 {
+                    1
+                } else {
+                    2
                 }
             }
+        };
+
+        let color = if self.optimize {
+            (info.color.r, info.color.g, info.color.b)
+        } else {
+            (0, 0, 0)
+        };
+
+        (tier, pass, brightness, color)
+    }
+
+    // clicks inside of the outlined regions with the bucket
+    fn fill_regions(
+        &mut self,
+        desktop: &dyn Desktop,
+        plan: &FillPlan,
+        image: &Image,
+        drawer_running: Arc<AtomicBool>,
+    ) {
+        let tools = match self.fill {
+            Some(tools) if !plan.seeds.is_empty() => tools,
+            _ => return,
+        };
 
-            draw_queue.draw(desktop, self, drawer_running.clone());
+        let mut seeds = plan.seeds.clone();
+        seeds.sort_by_key(|seed| seed.2.brightness());
+
+        // pixels inside of the outlines, the outlines are drawn already
+        let pixels = image
+            .enumerate_pixels()
+            .filter(|&(x, y, _)| {
+                plan.is_filled(x, y) && !plan.is_outline(x, y) && pixel_color(image, x, y) != WHITE
+            })
+            .count() as u32;
+
+        self.click_tool(desktop, tools.bucket);
+
+        for (index, (x, y, color)) in seeds.iter().rev().enumerate() {
+            if !self.keep_running(desktop, &drawer_running) {
+                return;
+            }
+            if self.skipping() {
+                self.advance(share(pixels, index, seeds.len()), color);
+                continue;
+            }
+
+            if let Err(err) = self.pick_color(desktop, color) {
+                self.cancel(&drawer_running, err);
+                return;
+            }
+
+            if let Some((screen_x, screen_y)) = self.screen_position(*x, *y) {
+                self.move_cursor(desktop, screen_x, screen_y);
+                desktop.left_click(ClickType::Once);
+                self.tally.clicks += 1;
+            }
+
+            desktop.sleep(Duration::from_millis(self.delay));
+            self.advance(share(pixels, index, seeds.len()), color);
+        }
+
+        self.click_tool(desktop, tools.brush);
+    }
+
+    // strokes the fine passes need for the selected pixels
+    fn count_strokes<F>(&self, image: &Image, selected: F) -> usize
+    where
+        F: Fn(u32, u32) -> bool,
+    {
+        let mut draw_queue = DrawQueue::new();
+
+        if self.checkerboard {
+            self.queue_pixels(&mut draw_queue, image, self.strokes, |x, y| {
+                (x + y) % 2 == 1 && selected(x, y)
+            });
+            self.queue_pixels(&mut draw_queue, image, self.strokes, |x, y| {
+                (x + y) % 2 == 0 && selected(x, y)
+            });
         } else {
-            for y in 0..image.height() {
-                for x in 0..image.width() {
-                    let pixel = image.get_pixel(x, y).to_rgb();
-                    let color = Color {
-                        r: pixel[0],
-                        g: pixel[1],
-                        b: pixel[2],
+            self.queue_pixels(&mut draw_queue, image, self.strokes, selected);
+        }
+
+        draw_queue
+            .queue
+            .iter()
+            .filter(|(_, info)| info.color != WHITE)
+            .count()
+    }
+
+    // paints the strokes of a large brush, darkest first like the pixels
+    fn draw_coarse(
+        &mut self,
+        desktop: &dyn Desktop,
+        mut strokes: Vec<CoarseStroke>,
+        pixels: u32,
+        drawer_running: Arc<AtomicBool>,
+    ) {
+        strokes.sort_by_key(|stroke| stroke.color.brightness());
+
+        for (index, stroke) in strokes.iter().enumerate() {
+            if !self.keep_running(desktop, &drawer_running) {
+                return;
+            }
+            if self.skipping() {
+                self.advance(share(pixels, index, strokes.len()), &stroke.color);
+                continue;
+            }
+
+            if let Err(err) = self.pick_color(desktop, &stroke.color) {
+                self.cancel(&drawer_running, err);
+                return;
+            }
+
+            self.move_cursor(
+                desktop,
+                self.drawing_area.x + stroke.start.0,
+                self.drawing_area.y + stroke.start.1,
+            );
+            if stroke.start == stroke.end {
+                desktop.left_click(ClickType::Once);
+            } else {
+                desktop.left_click(ClickType::Down);
+                self.move_cursor(
+                    desktop,
+                    self.drawing_area.x + stroke.end.0,
+                    self.drawing_area.y + stroke.end.1,
+                );
+                desktop.left_click(ClickType::Up);
+            }
+            self.tally.clicks += 1;
+
+            desktop.sleep(Duration::from_millis(self.delay));
+            self.advance(share(pixels, index, strokes.len()), &stroke.color);
+        }
+    }
+
+    // whether the next step was done before the drawing was resumed
+    fn skipping(&self) -> bool {
+        self.tally.steps < self.skip
+    }
+
+    // waits while the drawing is paused, false if it was cancelled
+    fn keep_running(&mut self, desktop: &dyn Desktop, drawer_running: &AtomicBool) -> bool {
+        let mut reason = "Paused";
+
+        if self.pointer_moved(desktop) {
+            reason = "The mouse was moved";
+
+            if self.pointer_guard == PointerGuard::Abort {
+                self.cancel(drawer_running, reason.into());
+            } else {
+                self.paused.store(true, Ordering::Relaxed);
+            }
+        }
+
+        if self.paused.load(Ordering::Relaxed) && drawer_running.load(Ordering::Relaxed) {
+            self.report(DrawEvent::Paused(reason.into()));
+
+            while self.paused.load(Ordering::Relaxed) && drawer_running.load(Ordering::Relaxed) {
+                thread::sleep(PAUSE_POLL_INTERVAL);
+            }
+
+            // select the color again before continuing with the same stroke,
+            // the mouse is wherever the user left it
+            self.last_color = None;
+            self.last_cursor = None;
+            self.report(DrawEvent::Resumed);
+        }
+
+        drawer_running.load(Ordering::Relaxed)
+    }
+
+    // whether the mouse isn't where the drawer put it anymore
+    fn pointer_moved(&self, desktop: &dyn Desktop) -> bool {
+        if self.pointer_guard == PointerGuard::Off {
+            return false;
+        }
+
+        match (self.last_cursor, desktop.pointer_position()) {
+            (Some(expected), Some(actual)) => {
+                expected.0.abs_diff(actual.0) > POINTER_TOLERANCE
+                    || expected.1.abs_diff(actual.1) > POINTER_TOLERANCE
+            }
+            _ => false,
+        }
+    }
+
+    fn move_cursor(&mut self, desktop: &dyn Desktop, x: u32, y: u32) {
+        self.last_cursor = Some((x, y));
+        desktop.move_cursor(x, y);
+    }
+
+    fn report(&self, event: DrawEvent) {
+        if let Some(events) = &self.events {
+            events(event);
+        }
+    }
+
+    // clicks a brush size button if the brush isn't selected yet
+    fn select_brush(&mut self, desktop: &dyn Desktop, brush: Brush) {
+        if self.last_brush != Some(brush) {
+            self.last_brush = Some(brush);
+            self.click_tool(desktop, (brush.x, brush.y));
+        }
+    }
+
+    fn click_tool(&mut self, desktop: &dyn Desktop, (x, y): (u32, u32)) {
+        self.move_cursor(desktop, x, y);
+        desktop.left_click(ClickType::Once);
+
+        desktop.sleep(Duration::from_millis(self.delay));
+    }
+
+    // selects a color in the palette or the color picker if it isn't selected yet,
+    // Err if the palette doesn't have it
+    fn pick_color(&mut self, desktop: &dyn Desktop, color: &Color) -> Result<(), String> {
+        if self.last_color.as_ref() == Some(color) {
+            return Ok(());
+        }
+
+        match self.picker {
+            Some(picker) => {
+                self.click_tool(desktop, picker.button);
+
+                // a triple click selects the old value so typing replaces it
+                self.move_cursor(desktop, picker.entry.0, picker.entry.1);
+                for _ in 0..3 {
+                    desktop.left_click(ClickType::Once);
+                }
+                for key in color.to_string().chars().chain(Some('\n')) {
+                    desktop.type_key(key);
+                }
+            }
+            None => {
+                let color_coord = *self
+                    .color_coords
+                    .get(color)
+                    .ok_or_else(|| format!("The palette has no {}", color))?;
+
+                self.move_cursor(desktop, color_coord.x, color_coord.y);
+                desktop.left_click(ClickType::Once);
+            }
+        }
+        self.last_color = Some(color.clone());
+        self.tally.color_switches += 1;
+
+        desktop.sleep(Duration::from_millis(self.delay));
+        Ok(())
+    }
+
+    // stops the drawing and remembers why
+    fn cancel(&mut self, drawer_running: &AtomicBool, reason: String) {
+        drawer_running.store(false, Ordering::Relaxed);
+        self.stop_reason = Some(reason);
+    }
+
+    // merges the selected pixels into strokes and pushes them onto the queue
+    fn queue_pixels<F>(
+        &self,
+        draw_queue: &mut DrawQueue,
+        image: &Image,
+        strokes: Strokes,
+        selected: F,
+    ) where
+        F: Fn(u32, u32) -> bool,
+    {
+        let (outer, inner) = match strokes {
+            Strokes::Columns => (image.width(), image.height()),
+            Strokes::Pixels | Strokes::Rows => (image.height(), image.width()),
+        };
+
+        for a in 0..outer {
+            let mut stroke: Option<DrawInfo> = None;
+
+            for b in 0..inner {
+                let (x, y) = match strokes {
+                    Strokes::Columns => (a, b),
+                    Strokes::Pixels | Strokes::Rows => (b, a),
+                };
+
+                if !selected(x, y) {
+                    continue;
+                }
+
+                let color = pixel_color(image, x, y);
+
+                // extend the current stroke if the pixel directly continues it
+                if let Some(current) = &mut stroke {
+                    let continues = match strokes {
+                        Strokes::Pixels => false,
+                        Strokes::Rows => current.end_x + 1 == x,
+                        Strokes::Columns => current.end_y + 1 == y,
                     };
 
-                    draw_queue.push(DrawInfo { x, y, color });
+                    if continues && current.color == color {
+                        current.end_x = x;
+                        current.end_y = y;
+                        continue;
+                    }
                 }
+
+                if let Some(finished) = stroke.replace(DrawInfo {
+                    x,
+                    y,
+                    end_x: x,
+                    end_y: y,
+                    path: Vec::new(),
+                    color,
+                }) {
+                    draw_queue.push(finished);
+                }
+            }
+
+            if let Some(finished) = stroke {
+                draw_queue.push(finished);
             }
+        }
+    }
+
+    // offset of the center of a pixel inside the drawing area,
+    // a pixel covers `step` screen pixels starting at `pixel * step`
+    fn drawing_area_offset(&self, pixel: u32) -> u32 {
+        ((f64::from(pixel) * self.step) + (self.step - 1.0) / 2.0).round() as u32
+    }
+
+    // position of a pixel on the screen, None if it's outside of the drawing area
+    fn screen_position(&self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let drawing_area_x = self.drawing_area_offset(x);
+        let drawing_area_y = self.drawing_area_offset(y);
 
-            draw_queue.draw(desktop, self, drawer_running.clone());
+        if drawing_area_x < self.drawing_area.width && drawing_area_y < self.drawing_area.height {
+            Some((
+                self.drawing_area.x + drawing_area_x,
+                self.drawing_area.y + drawing_area_y,
+            ))
+        } else {
+            None
         }
     }
+
+    // end of a stroke on the screen, clipped to the drawing area
+    fn clipped_screen_position(&self, x: u32, y: u32) -> (u32, u32) {
+        (
+            self.drawing_area.x
+                + self
+                    .drawing_area_offset(x)
+                    .min(self.drawing_area.width.saturating_sub(1)),
+            self.drawing_area.y
+                + self
+                    .drawing_area_offset(y)
+                    .min(self.drawing_area.height.saturating_sub(1)),
+        )
+    }
+}
+
+// part of `pixels` that belongs to the stroke at `index` when they are split evenly
+fn share(pixels: u32, index: usize, count: usize) -> u32 {
+    let until = |index: usize| (u64::from(pixels) * index as u64 / count as u64) as u32;
+    until(index + 1) - until(index)
+}
+
+fn pixel_color(image: &Image, x: u32, y: u32) -> Color {
+    let pixel = image.get_pixel(x, y).to_rgb();
+
+    Color {
+        r: pixel[0],
+        g: pixel[1],
+        b: pixel[2],
+    }
 }
 
+// importance, pass, brightness and color
+type Rank = (u32, u32, u32, (u8, u8, u8));
+
+// a single pixel or a stroke from (x, y) to (end_x, end_y),
+// straight or through the corners of a traced line
 struct DrawInfo {
     x: u32,
     y: u32,
+    end_x: u32,
+    end_y: u32,
+    path: Vec<(u32, u32)>,
     color: Color,
 }
 
+impl DrawInfo {
+    // straight strokes are either horizontal or vertical, lines are represented by their corners
+    fn pixels(&self) -> Vec<(u32, u32)> {
+        if self.path.is_empty() {
+            let xs = self.x.min(self.end_x)..=self.x.max(self.end_x);
+            (self.y.min(self.end_y)..=self.y.max(self.end_y))
+                .flat_map(|y| xs.clone().map(move |x| (x, y)))
+                .collect()
+        } else {
+            let mut pixels = vec![(self.x, self.y)];
+            pixels.extend(&self.path);
+            pixels.push((self.end_x, self.end_y));
+            pixels
+        }
+    }
+
+    // pixels the stroke paints
+    fn length(&self) -> u32 {
+        let mut points = vec![(self.x, self.y)];
+        points.extend(&self.path);
+        points.push((self.end_x, self.end_y));
+
+        1 + points
+            .windows(2)
+            .map(|pair| {
+                pair[0]
+                    .0
+                    .abs_diff(pair[1].0)
+                    .max(pair[0].1.abs_diff(pair[1].1))
+            })
+            .sum::<u32>()
+    }
+}
+
+impl Stroke for DrawInfo {
+    fn color(&self) -> &Color {
+        &self.color
+    }
+
+    fn start(&self) -> (u32, u32) {
+        (self.x, self.y)
+    }
+
+    fn end(&self) -> (u32, u32) {
+        (self.end_x, self.end_y)
+    }
+
+    fn reverse(&mut self) {
+        std::mem::swap(&mut self.x, &mut self.end_x);
+        std::mem::swap(&mut self.y, &mut self.end_y);
+        self.path.reverse();
+    }
+}
+
 struct DrawQueue {
-    queue: Vec<DrawInfo>,
+    // strokes with the pass they were queued in
+    queue: Vec<(u32, DrawInfo)>,
+    pass: u32,
 }
 
 impl DrawQueue {
     fn new() -> DrawQueue {
-        DrawQueue { queue: Vec::new() }
+        DrawQueue {
+            queue: Vec::new(),
+            pass: 0,
+        }
     }
 
     fn push(&mut self, info: DrawInfo) {
-        self.queue.insert(0, info);
+        self.queue.insert(0, (self.pass, info));
+    }
+
+    // strokes pushed from now on are drawn after the ones before with the same importance
+    fn next_pass(&mut self) {
+        self.pass += 1;
     }
 
-    fn draw(&mut self, desktop: &Desktop, drawer: &mut Drawer, drawer_running: Arc<AtomicBool>) {
-        self.queue
-            .sort_by(|a, b| b.color.brightness().cmp(&a.color.brightness()));
+    fn draw(
+        &mut self,
+        desktop: &dyn Desktop,
+        drawer: &mut Drawer,
+        edges: Option<&EdgeMap>,
+        drawer_running: Arc<AtomicBool>,
+    ) {
+        let mut queue: Vec<(Rank, DrawInfo)> = self
+            .queue
+            .drain(..)
+            .map(|(pass, info)| (drawer.rank(pass, &info, edges), info))
+            .collect();
+        queue.sort_by_key(|(rank, _)| Reverse(*rank));
+        self.pass = 0;
 
-        while let Some(info) = self.queue.pop() {
-            let running = drawer_running.load(Ordering::Relaxed);
-            if !running {
+        if drawer.optimize {
+            queue = plan(queue, drawer);
+        }
+
+        while let Some((_, info)) = queue.pop() {
+            if !drawer.keep_running(desktop, &drawer_running) {
                 return;
             }
 
             if info.color == WHITE {
                 continue;
             }
+            if drawer.skipping() {
+                drawer.advance(info.length(), &info.color);
+                continue;
+            }
 
-            let color_coord = drawer.color_coords[&info.color];
+            if let Err(err) = drawer.pick_color(desktop, &info.color) {
+                drawer.cancel(&drawer_running, err);
+                return;
+            }
 
-            // color changed
-            if color_coord != drawer.last_color {
-                drawer.last_color = color_coord;
+            // continue drawing with new color
+            if let Some((start_x, start_y)) = drawer.screen_position(info.x, info.y) {
+                drawer.move_cursor(desktop, start_x, start_y);
 
-                // pick color
-                desktop.move_cursor(color_coord.x, color_coord.y);
-                desktop.left_click(ClickType::Once);
+                if (info.x, info.y) == (info.end_x, info.end_y) && info.path.is_empty() {
+                    desktop.left_click(ClickType::Once);
+                } else {
+                    let (end_x, end_y) = drawer.clipped_screen_position(info.end_x, info.end_y);
 
-                thread::sleep(Duration::from_millis(drawer.delay));
+                    desktop.left_click(ClickType::Down);
+                    for &(x, y) in &info.path {
+                        let (x, y) = drawer.clipped_screen_position(x, y);
+                        drawer.move_cursor(desktop, x, y);
+                    }
+                    drawer.move_cursor(desktop, end_x, end_y);
+                    desktop.left_click(ClickType::Up);
+                }
+                drawer.tally.clicks += 1;
             }
 
-            // continue drawing with new color
-            let new_drawing_area_x = ((f64::from(info.x) * drawer.step) + 1.0).round() as u32;
-            let new_drawing_area_y = ((f64::from(info.y) * drawer.step) + 1.0).round() as u32;
-            if new_drawing_area_x <= drawer.drawing_area.width
-                && new_drawing_area_y <= drawer.drawing_area.height
-            {
-                desktop.move_cursor(
-                    drawer.drawing_area.x + new_drawing_area_x,
-                    drawer.drawing_area.y + new_drawing_area_y,
-                );
-                desktop.left_click(ClickType::Once);
+            desktop.sleep(Duration::from_millis(drawer.delay));
+            drawer.advance(info.length(), &info.color);
+        }
+    }
+}
+
+// orders the strokes of every rank by distance, the queue is drawn from the back
+fn plan(mut queue: Vec<(Rank, DrawInfo)>, drawer: &Drawer) -> Vec<(Rank, DrawInfo)> {
+    queue.reverse();
+    queue.retain(|(_, info)| info.color != WHITE);
+
+    let mut groups: Vec<(Rank, Vec<DrawInfo>)> = Vec::new();
+    for (rank, info) in queue {
+        match groups.last_mut() {
+            Some((last, strokes)) if *last == rank => strokes.push(info),
+            _ => groups.push((rank, vec![info])),
+        }
+    }
+
+    let before = planner::cost(groups.iter().flat_map(|(_, strokes)| strokes));
+
+    let mut planned = Vec::new();
+    for (rank, strokes) in groups {
+        let from = planned
+            .last()
+            .map(|(_, info): &(Rank, DrawInfo)| info.end());
+        let strokes = planner::order(strokes, from);
+        planned.extend(strokes.into_iter().map(|info| (rank, info)));
+    }
+
+    let after = planner::cost(planned.iter().map(|(_, info)| info));
+    if !planned.is_empty() {
+        drawer.report(DrawEvent::Planned(before, after));
+    }
+
+    planned.reverse();
+    planned
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        brushes::Brush,
+        color_distance::ColorDistance,
+        colors::*,
+        desktop::{InputEvent, RecordingDesktop},
+        image_converter::{self, Dithering, SkribblColorMap},
+    };
+    use image::{DynamicImage, ImageBuffer, Rgb};
+    use std::sync::Mutex;
+    use InputEvent::*;
+
+    // white is at (12, 22), the other colors are 4px apart
+    const COLOR_BOX: Box = Box {
+        x: 10,
+        y: 20,
+        width: 4,
+        height: 4,
+    };
+
+    fn drawer(checkerboard: bool, strokes: Strokes) -> Drawer {
+        let drawing_area = Box {
+            x: 100,
+            y: 200,
+            width: 30,
+            height: 30,
+        };
+
+        Drawer::new(drawing_area, COLOR_BOX, checkerboard, strokes, 0, 3.0)
+    }
+
+    fn image(rows: &[&[Color]]) -> Image {
+        ImageBuffer::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            let color = &rows[y as usize][x as usize];
+            Rgb([color.r, color.g, color.b])
+        })
+    }
+
+    fn draw(mut drawer: Drawer, image: &Image) -> Vec<InputEvent> {
+        let desktop = RecordingDesktop::new();
+        drawer.draw(&desktop, image, Arc::new(AtomicBool::new(true)));
+        desktop.events()
+    }
+
+    fn click(x: u32, y: u32) -> Vec<InputEvent> {
+        vec![Move(x, y), Press, Release]
+    }
+
+    fn stroke(start: (u32, u32), end: (u32, u32)) -> Vec<InputEvent> {
+        vec![Move(start.0, start.1), Press, Move(end.0, end.1), Release]
+    }
+
+    #[test]
+    fn clicks_pixel_centers() {
+        let events = draw(drawer(false, Strokes::Pixels), &image(&[&[BLACK, BLACK]]));
+
+        let expected = [click(12, 26), click(101, 201), click(104, 201)].concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn skips_white_pixels() {
+        let events = draw(
+            drawer(false, Strokes::Pixels),
+            &image(&[&[WHITE, BLACK], &[WHITE, WHITE]]),
+        );
+
+        let expected = [click(12, 26), click(104, 201)].concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn picks_colors_once_darkest_first() {
+        let events = draw(
+            drawer(false, Strokes::Pixels),
+            &image(&[&[LIGHT_RED, BLACK, LIGHT_RED]]),
+        );
+
+        let expected = [
+            click(12, 26),
+            click(104, 201),
+            click(20, 22),
+            click(101, 201),
+            click(107, 201),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn types_colors_into_the_picker() {
+        let picker = ColorPicker {
+            button: (50, 50),
+            entry: (50, 60),
+        };
+        let events = draw(
+            drawer(false, Strokes::Pixels).with_color_picker(picker),
+            &image(&[&[LIGHT_RED, LIGHT_RED]]),
+        );
+
+        let typed = "#ef130b\n".chars().map(Key).collect::<Vec<_>>();
+        let expected = [
+            click(50, 50),
+            vec![Move(50, 60)],
+            vec![Press, Release, Press, Release, Press, Release],
+            typed,
+            click(101, 201),
+            click(104, 201),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn merges_rows_into_strokes() {
+        let events = draw(
+            drawer(false, Strokes::Rows),
+            &image(&[&[BLACK, BLACK, BLACK], &[BLACK, WHITE, BLACK]]),
+        );
+
+        let expected = [
+            click(12, 26),
+            stroke((101, 201), (107, 201)),
+            click(101, 204),
+            click(107, 204),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn merges_columns_into_strokes() {
+        let events = draw(
+            drawer(false, Strokes::Columns),
+            &image(&[&[BLACK, BLACK], &[BLACK, WHITE]]),
+        );
+
+        let expected = [
+            click(12, 26),
+            stroke((101, 201), (101, 204)),
+            click(104, 201),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn checkerboard_draws_two_passes() {
+        let events = draw(
+            drawer(true, Strokes::Rows),
+            &image(&[&[BLACK, BLACK], &[BLACK, BLACK]]),
+        );
+
+        let expected = [
+            click(12, 26),
+            // first pass
+            click(104, 201),
+            click(101, 204),
+            // second pass
+            click(101, 201),
+            click(104, 204),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn checkerboard_clicks_every_pixel() {
+        let image = image(&[&[BLACK; 6], &[BLACK; 6]]);
+        let all = |_, _| true;
+
+        assert_eq!(drawer(false, Strokes::Rows).count_strokes(&image, all), 2);
+        assert_eq!(
+            drawer(false, Strokes::Columns).count_strokes(&image, all),
+            6
+        );
+        for &strokes in Strokes::ALL.iter() {
+            assert_eq!(drawer(true, strokes).count_strokes(&image, all), 12);
+        }
+    }
+
+    #[test]
+    fn stays_inside_drawing_area() {
+        let drawing_area = || Box {
+            x: 100,
+            y: 200,
+            width: 6,
+            height: 6,
+        };
+        let image = image(&[&[BLACK, BLACK, BLACK]]);
+
+        let pixels = Drawer::new(drawing_area(), COLOR_BOX, false, Strokes::Pixels, 0, 3.0);
+        let expected = [click(12, 26), click(101, 201), click(104, 201)].concat();
+        assert_eq!(draw(pixels, &image), expected);
+
+        let rows = Drawer::new(drawing_area(), COLOR_BOX, false, Strokes::Rows, 0, 3.0);
+        let expected = [click(12, 26), stroke((101, 201), (105, 201))].concat();
+        assert_eq!(draw(rows, &image), expected);
+    }
+
+    #[test]
+    fn stops_when_not_running() {
+        let desktop = RecordingDesktop::new();
+        let mut drawer = drawer(false, Strokes::Pixels);
+
+        drawer.draw(
+            &desktop,
+            &image(&[&[BLACK]]),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        assert_eq!(desktop.events(), vec![]);
+    }
+
+    // pauses the drawing after the first pixel and resumes it shortly after
+    struct PausingDesktop {
+        desktop: RecordingDesktop,
+        paused: Arc<AtomicBool>,
+    }
+
+    impl Desktop for PausingDesktop {
+        fn move_cursor(&self, x: u32, y: u32) {
+            self.desktop.move_cursor(x, y);
+        }
+
+        fn press(&self) {
+            self.desktop.press();
+        }
+
+        fn release(&self) {
+            self.desktop.release();
+
+            if self.desktop.events().len() == 6 {
+                self.paused.store(true, Ordering::Relaxed);
+
+                let paused = self.paused.clone();
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(100));
+                    paused.store(false, Ordering::Relaxed);
+                });
+            }
+        }
+
+        fn type_key(&self, key: char) {
+            self.desktop.type_key(key);
+        }
+    }
+
+    #[test]
+    fn resumes_with_the_color_selected_again() {
+        let paused = Arc::new(AtomicBool::new(false));
+        let desktop = PausingDesktop {
+            desktop: RecordingDesktop::new(),
+            paused: paused.clone(),
+        };
+        let mut drawer = drawer(false, Strokes::Pixels).with_pause(paused);
+
+        drawer.draw(
+            &desktop,
+            &image(&[&[BLACK, BLACK]]),
+            Arc::new(AtomicBool::new(true)),
+        );
+
+        let expected = [
+            click(12, 26),
+            click(101, 201),
+            click(12, 26),
+            click(104, 201),
+        ]
+        .concat();
+        assert_eq!(desktop.desktop.events(), expected);
+    }
+
+    // someone else moves the mouse to the corner after the first pixel
+    struct MovedPointerDesktop {
+        desktop: RecordingDesktop,
+    }
+
+    impl Desktop for MovedPointerDesktop {
+        fn move_cursor(&self, x: u32, y: u32) {
+            self.desktop.move_cursor(x, y);
+        }
+
+        fn press(&self) {
+            self.desktop.press();
+        }
+
+        fn release(&self) {
+            self.desktop.release();
+        }
+
+        fn type_key(&self, key: char) {
+            self.desktop.type_key(key);
+        }
+
+        fn pointer_position(&self) -> Option<(u32, u32)> {
+            let events = self.desktop.events();
+
+            if events.len() >= 6 {
+                Some((0, 0))
+            } else {
+                events.iter().rev().find_map(|event| match *event {
+                    Move(x, y) => Some((x, y)),
+                    _ => None,
+                })
+            }
+        }
+    }
+
+    #[test]
+    fn cancels_when_the_mouse_is_moved() {
+        let desktop = MovedPointerDesktop {
+            desktop: RecordingDesktop::new(),
+        };
+        let mut drawer = drawer(false, Strokes::Pixels).with_pointer_guard(PointerGuard::Abort);
+        let running = Arc::new(AtomicBool::new(true));
+
+        drawer.draw(&desktop, &image(&[&[BLACK, BLACK]]), running.clone());
+
+        let expected = [click(12, 26), click(101, 201)].concat();
+        assert_eq!(desktop.desktop.events(), expected);
+        assert_eq!(drawer.stop_reason(), Some("The mouse was moved"));
+        assert!(!running.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn stops_on_colors_the_palette_doesnt_have() {
+        let palette = Palette {
+            name: "no black".into(),
+            rows: vec![vec![WHITE, LIGHT_RED]],
+        };
+        let mut drawer = drawer(false, Strokes::Pixels).with_palette(&palette);
+        let desktop = RecordingDesktop::new();
+        let running = Arc::new(AtomicBool::new(true));
+
+        drawer.draw(&desktop, &image(&[&[BLACK, BLACK]]), running.clone());
+
+        assert!(desktop.events().is_empty());
+        assert_eq!(drawer.stop_reason(), Some("The palette has no #000000"));
+        assert!(!running.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn pauses_when_the_mouse_is_moved() {
+        let desktop = MovedPointerDesktop {
+            desktop: RecordingDesktop::new(),
+        };
+        let paused = Arc::new(AtomicBool::new(false));
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut drawer = drawer(false, Strokes::Pixels)
+            .with_pointer_guard(PointerGuard::Pause)
+            .with_pause(paused.clone())
+            .with_events({
+                let events = events.clone();
+                move |event| events.lock().unwrap().push(event)
+            });
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            paused.store(false, Ordering::Relaxed);
+        });
+        drawer.draw(
+            &desktop,
+            &image(&[&[BLACK, BLACK]]),
+            Arc::new(AtomicBool::new(true)),
+        );
+
+        let expected = [
+            click(12, 26),
+            click(101, 201),
+            click(12, 26),
+            click(104, 201),
+        ]
+        .concat();
+        assert_eq!(desktop.desktop.events(), expected);
+        let events = events.lock().unwrap();
+        assert_eq!(
+            events[..2],
+            [
+                DrawEvent::Paused("The mouse was moved".into()),
+                DrawEvent::Resumed
+            ]
+        );
+        assert_eq!(drawer.stop_reason(), None);
+    }
+
+    #[test]
+    fn reports_progress_and_a_summary() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut drawer = drawer(false, Strokes::Rows).with_events({
+            let events = events.clone();
+            move |event| events.lock().unwrap().push(event)
+        });
+        let image = image(&[&[BLACK, BLACK, WHITE, LIGHT_RED]]);
+
+        let desktop = RecordingDesktop::new();
+        let summary = drawer.draw(&desktop, &image, Arc::new(AtomicBool::new(true)));
+
+        assert_eq!(
+            (
+                summary.done,
+                summary.total,
+                summary.clicks,
+                summary.color_switches
+            ),
+            (3, 3, 2, 2)
+        );
+        // the last stroke always reports, no matter how fast it was
+        let last = events.lock().unwrap().last().cloned();
+        match last {
+            Some(DrawEvent::Progress(progress)) => {
+                assert_eq!((progress.done, progress.total), (3, 3));
+                assert_eq!(progress.color, LIGHT_RED);
+                assert_eq!(progress.fraction(), 1.0);
+                assert_eq!(progress.remaining(), Some(Duration::from_secs(0)));
             }
+            event => panic!("expected progress, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn splits_pixels_evenly() {
+        let shares: Vec<u32> = (0..3).map(|index| share(10, index, 3)).collect();
+
+        assert_eq!(shares, vec![3, 3, 4]);
+    }
+
+    #[test]
+    fn draws_converted_image() {
+        let source = ImageBuffer::from_fn(3, 1, |x, _| match x {
+            0 => Rgb([10, 10, 10]),
+            1 => Rgb([250, 250, 250]),
+            _ => Rgb([230, 30, 20]),
+        });
+        let converted = image_converter::convert(
+            DynamicImage::ImageRgb8(source),
+            Dithering::None,
+            &SkribblColorMap::new(&Palette::skribbl(), ColorDistance::Rgb),
+            false,
+            1.0,
+            3,
+            1,
+        );
+
+        let events = draw(drawer(false, Strokes::Rows), &converted);
+
+        let expected = [
+            click(12, 26),
+            click(101, 201),
+            click(20, 22),
+            click(107, 201),
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn fills_large_regions_with_the_bucket() {
+        let tools = Tools {
+            brush: (300, 20),
+            bucket: (330, 20),
+        };
+        let black = [BLACK; 8];
+        let image = image(&[&black[..]; 8]);
+
+        let events = draw(drawer(false, Strokes::Rows).with_fill(tools), &image);
+
+        // the outline is two rows and two columns
+        assert_eq!(events[..3], click(12, 26)[..]);
+        assert_eq!(
+            events.iter().filter(|&&event| event == Press).count(),
+            1 + 4 + 3
+        );
+        assert_eq!(
+            events[events.len() - 9..],
+            [click(330, 20), click(104, 204), click(300, 20)].concat()[..]
+        );
+    }
 
-            thread::sleep(Duration::from_millis(drawer.delay));
+    #[test]
+    fn paints_broad_regions_with_a_large_brush() {
+        let brushes = vec![
+            Brush {
+                x: 80,
+                y: 20,
+                size: 12,
+            },
+            Brush {
+                x: 50,
+                y: 20,
+                size: 3,
+            },
+        ];
+        let black = [BLACK; 5];
+        let image = image(&[&black[..]; 5]);
+
+        let events = draw(
+            drawer(false, Strokes::Pixels).with_brushes(brushes.clone()),
+            &image,
+        );
+
+        // the large brush covers the middle, the edges are left for the small one
+        let edges = (0..5)
+            .flat_map(|y| (0..5).map(move |x| (x, y)))
+            .filter(|&(x, y)| x % 4 == 0 || y % 4 == 0)
+            .flat_map(|(x, y)| click(101 + x * 3, 201 + y * 3));
+        let expected = [
+            click(50, 20),
+            click(80, 20),
+            click(12, 26),
+            click(107, 207),
+            click(50, 20),
+        ]
+        .concat()
+        .into_iter()
+        .chain(edges)
+        .collect::<Vec<_>>();
+        assert_eq!(events, expected);
+
+        // rows are cheaper than a stamp and the rows it leaves behind
+        let events = draw(drawer(false, Strokes::Rows).with_brushes(brushes), &image);
+        assert_eq!(events[..6], [click(50, 20), click(12, 26)].concat()[..]);
+        assert!(!events.contains(&Move(80, 20)));
+    }
+
+    #[test]
+    fn importance_draws_edges_before_flat_regions() {
+        let row = [[LIGHT_GREY; 5].as_ref(), [LIGHT_YELLOW; 5].as_ref()].concat();
+        let image = image(&[&row[..]; 4]);
+        let position = |events: &[InputEvent], x: u32| {
+            events
+                .windows(2)
+                .position(|window| window == [Move(x, 201), Press])
+                .unwrap()
+        };
+
+        let brightness = draw(drawer(false, Strokes::Pixels), &image);
+        assert!(position(&brightness, 101) < position(&brightness, 116));
+
+        let importance = draw(
+            drawer(false, Strokes::Pixels).with_order(DrawOrder::Importance),
+            &image,
+        );
+        // the yellow next to the grey comes before the rest of the grey
+        assert!(position(&importance, 116) < position(&importance, 101));
+        // the flat yellow highlight comes last
+        assert_eq!(importance[importance.len() - 3..], click(128, 210)[..]);
+    }
+
+    #[test]
+    fn draws_lines_as_drags() {
+        let image = image(&[
+            &[BLACK, BLACK, BLACK, WHITE],
+            &[WHITE, WHITE, BLACK, WHITE],
+            &[WHITE, WHITE, BLACK, WHITE],
+        ]);
+
+        let events = draw(drawer(false, Strokes::Pixels).with_lines(), &image);
+
+        let expected = [
+            click(12, 26),
+            vec![
+                Move(101, 201),
+                Press,
+                Move(107, 201),
+                Move(107, 207),
+                Release,
+            ],
+        ]
+        .concat();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn plans_rows_back_and_forth() {
+        let image = image(&[&[BLACK, BLACK, BLACK], &[BLACK, BLACK, BLACK]]);
+        let planned = Arc::new(Mutex::new(Vec::new()));
+        let drawer = drawer(false, Strokes::Rows).with_optimize().with_events({
+            let planned = planned.clone();
+            move |event| {
+                if let DrawEvent::Planned(before, after) = event {
+                    planned.lock().unwrap().push((before, after));
+                }
+            }
+        });
+
+        let events = draw(drawer, &image);
+
+        let expected = [
+            click(12, 26),
+            vec![
+                Move(101, 201),
+                Press,
+                Move(107, 201),
+                Release,
+                Move(107, 204),
+                Press,
+                Move(101, 204),
+                Release,
+            ],
+        ]
+        .concat();
+        assert_eq!(events, expected);
+        assert_eq!(
+            *planned.lock().unwrap(),
+            vec![(
+                Cost {
+                    travel: 2,
+                    color_switches: 1
+                },
+                Cost {
+                    travel: 1,
+                    color_switches: 1
+                }
+            )]
+        );
+    }
+
+    #[test]
+    fn resumes_after_the_done_steps() {
+        let image = image(&[&[BLACK, BLACK, WHITE], &[WHITE, LIGHT_RED, LIGHT_RED]]);
+        let desktop = RecordingDesktop::new();
+        let mut drawer = drawer(false, Strokes::Rows).with_skip(1);
+
+        let summary = drawer.draw(&desktop, &image, Arc::new(AtomicBool::new(true)));
+
+        let expected = [
+            click(20, 22),
+            vec![Move(104, 204), Press, Move(107, 204), Release],
+        ]
+        .concat();
+        assert_eq!(desktop.events(), expected);
+        assert_eq!((summary.steps, summary.clicks), (2, 1));
+        assert_eq!(summary.done, summary.total);
+    }
+
+    // hands out screenshots that were taken before, the last one again and again
+    struct CapturingDesktop {
+        recording: RecordingDesktop,
+        captures: Mutex<Vec<Image>>,
+    }
+
+    impl Desktop for CapturingDesktop {
+        fn move_cursor(&self, x: u32, y: u32) {
+            self.recording.move_cursor(x, y);
+        }
+
+        fn press(&self) {
+            self.recording.press();
         }
+
+        fn release(&self) {
+            self.recording.release();
+        }
+
+        fn type_key(&self, key: char) {
+            self.recording.type_key(key);
+        }
+
+        fn capture_area(&self, area: Box) -> Result<Image, String> {
+            let mut captures = self.captures.lock().unwrap();
+            let capture = if captures.len() > 1 {
+                captures.remove(0)
+            } else {
+                captures[0].clone()
+            };
+
+            assert_eq!(capture.dimensions(), (area.width, area.height));
+            Ok(capture)
+        }
+    }
+
+    #[test]
+    fn redraws_pixels_that_are_missing() {
+        let image = image(&[&[BLACK, BLACK]]);
+        // the click on the second pixel got lost the first time
+        let canvas = |pixels: u32| {
+            ImageBuffer::from_fn(30, 30, |x, y| {
+                if x < pixels * 3 && y < 3 {
+                    Rgb([0, 0, 0])
+                } else {
+                    Rgb([255, 255, 255])
+                }
+            })
+        };
+        let desktop = CapturingDesktop {
+            recording: RecordingDesktop::new(),
+            captures: Mutex::new(vec![canvas(1), canvas(2)]),
+        };
+        let verified = Arc::new(Mutex::new(Vec::new()));
+        let verifying_drawer = || {
+            let verified = verified.clone();
+            drawer(false, Strokes::Pixels)
+                .with_verify()
+                .with_events(move |event| {
+                    if let DrawEvent::Verified(result) = event {
+                        verified.lock().unwrap().push(result);
+                    }
+                })
+        };
+
+        verifying_drawer().draw(&desktop, &image, Arc::new(AtomicBool::new(true)));
+
+        let expected = [
+            click(12, 26),
+            click(101, 201),
+            click(104, 201),
+            click(104, 201),
+        ]
+        .concat();
+        assert_eq!(desktop.recording.events(), expected);
+        assert_eq!(*verified.lock().unwrap(), vec![Ok(1), Ok(0)]);
+
+        // without a screenshot the drawing is left as it is
+        verified.lock().unwrap().clear();
+        let events = draw(verifying_drawer(), &image);
+        assert_eq!(events, expected[..expected.len() - 3].to_vec());
+        assert!(matches!(verified.lock().unwrap()[..], [Err(_)]));
     }
 }
@@ -0,0 +1,444 @@
+use crate::image_converter::Image;
+use image::imageops;
+
+// blur before looking for edges, so dithering patterns don't count as edges
+const BLUR_SIGMA: f32 = 1.0;
+// change of the brightness per pixel that becomes a line in sketches
+const LINE_THRESHOLD: f32 = 20.0;
+// shorter lines are noise
+const MIN_LINE_PIXELS: usize = 5;
+// how far a traced line may be away from the pixels it follows
+const SIMPLIFY_TOLERANCE: f64 = 0.5;
+
+// offsets of the 8 neighbours, clockwise starting at the top
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (0, -1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+];
+
+/// How strongly the brightness changes at every pixel of an image
+pub struct EdgeMap {
+    width: u32,
+    magnitudes: Vec<f32>,
+}
+
+impl EdgeMap {
+    /// Change of the brightness per pixel, between 0 and about 360
+    pub fn magnitude(&self, x: u32, y: u32) -> f32 {
+        self.magnitudes[(y * self.width + x) as usize]
+    }
+}
+
+/// Finds the edges of an image with the Sobel operator
+pub fn sobel(image: &Image) -> EdgeMap {
+    let (width, height) = image.dimensions();
+    let blurred = imageops::blur(&imageops::grayscale(image), BLUR_SIGMA);
+
+    // pixels outside of the image repeat the closest edge pixel
+    let luma = |x: i64, y: i64| {
+        let x = x.clamp(0, i64::from(width) - 1) as u32;
+        let y = y.clamp(0, i64::from(height) - 1) as u32;
+
+        f32::from(blurred.get_pixel(x, y)[0])
+    };
+
+    let mut magnitudes = Vec::with_capacity((width * height) as usize);
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            let horizontal = luma(x + 1, y - 1) + 2.0 * luma(x + 1, y) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x - 1, y)
+                - luma(x - 1, y + 1);
+            let vertical = luma(x - 1, y + 1) + 2.0 * luma(x, y + 1) + luma(x + 1, y + 1)
+                - luma(x - 1, y - 1)
+                - 2.0 * luma(x, y - 1)
+                - luma(x + 1, y - 1);
+
+            // the kernels sum up the difference over two pixels four times
+            magnitudes.push(horizontal.hypot(vertical) / 8.0);
+        }
+    }
+
+    EdgeMap { width, magnitudes }
+}
+
+/// Lines one pixel wide along the edges of an image, true for pixels on a line
+pub fn lines(image: &Image) -> Vec<bool> {
+    let (width, height) = image.dimensions();
+    let edges = sobel(image);
+
+    let mut lines: Vec<bool> = edges
+        .magnitudes
+        .iter()
+        .map(|&magnitude| magnitude >= LINE_THRESHOLD)
+        .collect();
+    thin(&mut lines, width, height);
+    remove_specks(&mut lines, width, height);
+
+    lines
+}
+
+// neighbour in one of the 8 directions, None outside of the image
+fn neighbour(x: u32, y: u32, (dx, dy): (i64, i64), width: u32, height: u32) -> Option<(u32, u32)> {
+    let (x, y) = (i64::from(x) + dx, i64::from(y) + dy);
+
+    if x >= 0 && y >= 0 && x < i64::from(width) && y < i64::from(height) {
+        Some((x as u32, y as u32))
+    } else {
+        None
+    }
+}
+
+// Zhang-Suen thinning, peels off the sides of thick lines until only their middle is left
+fn thin(mask: &mut [bool], width: u32, height: u32) {
+    let set = |mask: &[bool], x: u32, y: u32, direction: usize| {
+        neighbour(x, y, NEIGHBOURS[direction], width, height)
+            .is_some_and(|(x, y)| mask[(y * width + x) as usize])
+    };
+
+    loop {
+        let mut changed = false;
+
+        for step in 0..2 {
+            let mut remove = Vec::new();
+
+            for y in 0..height {
+                for x in 0..width {
+                    if !mask[(y * width + x) as usize] {
+                        continue;
+                    }
+
+                    let around: Vec<bool> =
+                        (0..8).map(|direction| set(mask, x, y, direction)).collect();
+                    let count = around.iter().filter(|&&set| set).count();
+                    let transitions = (0..8)
+                        .filter(|&direction| !around[direction] && around[(direction + 1) % 8])
+                        .count();
+                    // north, east, south and west
+                    let (n, e, s, w) = (around[0], around[2], around[4], around[6]);
+                    let sides = if step == 0 {
+                        !(e && s && (n || w))
+                    } else {
+                        !(n && w && (e || s))
+                    };
+
+                    if (2..=6).contains(&count) && transitions == 1 && sides {
+                        remove.push((y * width + x) as usize);
+                    }
+                }
+            }
+
+            changed |= !remove.is_empty();
+            for index in remove {
+                mask[index] = false;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+}
+
+// removes groups of connected pixels that are too small to be a line
+fn remove_specks(mask: &mut [bool], width: u32, height: u32) {
+    let mut visited = vec![false; mask.len()];
+
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+
+        let mut group = vec![start];
+        let mut stack = vec![start];
+        visited[start] = true;
+
+        while let Some(index) = stack.pop() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+
+            for &direction in NEIGHBOURS.iter() {
+                if let Some((x, y)) = neighbour(x, y, direction, width, height) {
+                    let index = (y * width + x) as usize;
+
+                    if mask[index] && !visited[index] {
+                        visited[index] = true;
+                        group.push(index);
+                        stack.push(index);
+                    }
+                }
+            }
+        }
+
+        if group.len() < MIN_LINE_PIXELS {
+            for index in group {
+                mask[index] = false;
+            }
+        }
+    }
+}
+
+/// Follows connected pixels of the same color into polylines. Lines start at their ends
+/// where possible and are split at junctions, only the corners of a line are kept.
+pub fn trace<F>(image: &Image, selected: F) -> Vec<Vec<(u32, u32)>>
+where
+    F: Fn(u32, u32) -> bool,
+{
+    let (width, height) = image.dimensions();
+    let mut remaining: Vec<bool> = (0..width * height)
+        .map(|index| selected(index % width, index / width))
+        .collect();
+
+    // unvisited neighbours with the same color, the straight ones first
+    let next = |remaining: &[bool], x: u32, y: u32| {
+        let straight = NEIGHBOURS.iter().step_by(2);
+        let diagonal = NEIGHBOURS.iter().skip(1).step_by(2);
+
+        straight
+            .chain(diagonal)
+            .filter_map(|&direction| neighbour(x, y, direction, width, height))
+            .find(|&(other_x, other_y)| {
+                remaining[(other_y * width + other_x) as usize]
+                    && image.get_pixel(other_x, other_y) == image.get_pixel(x, y)
+            })
+    };
+
+    let ends: Vec<(u32, u32)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&(x, y)| remaining[(y * width + x) as usize])
+        .filter(|&(x, y)| {
+            NEIGHBOURS
+                .iter()
+                .filter_map(|&direction| neighbour(x, y, direction, width, height))
+                .filter(|&(other_x, other_y)| {
+                    remaining[(other_y * width + other_x) as usize]
+                        && image.get_pixel(other_x, other_y) == image.get_pixel(x, y)
+                })
+                .count()
+                == 1
+        })
+        .collect();
+    let everything = (0..height).flat_map(|y| (0..width).map(move |x| (x, y)));
+
+    let mut lines = Vec::new();
+    for (x, y) in ends.into_iter().chain(everything) {
+        if !remaining[(y * width + x) as usize] {
+            continue;
+        }
+
+        let mut line = vec![(x, y)];
+        remaining[(y * width + x) as usize] = false;
+
+        while let Some((next_x, next_y)) =
+            next(&remaining, line[line.len() - 1].0, line[line.len() - 1].1)
+        {
+            remaining[(next_y * width + next_x) as usize] = false;
+            line.push((next_x, next_y));
+        }
+
+        lines.push(simplify(&line));
+    }
+
+    lines
+}
+
+// Ramer-Douglas-Peucker, drops points that are close to the line between their neighbours
+fn simplify(line: &[(u32, u32)]) -> Vec<(u32, u32)> {
+    if line.len() < 3 {
+        return line.to_vec();
+    }
+
+    let (first, last) = (line[0], line[line.len() - 1]);
+    let (start_x, start_y) = (f64::from(first.0), f64::from(first.1));
+    let (dx, dy) = (f64::from(last.0) - start_x, f64::from(last.1) - start_y);
+    let length = dx.hypot(dy);
+
+    let distance = |&(x, y): &(u32, u32)| {
+        let (x, y) = (f64::from(x) - start_x, f64::from(y) - start_y);
+
+        if length == 0.0 {
+            x.hypot(y)
+        } else {
+            (x * dy - y * dx).abs() / length
+        }
+    };
+
+    let (farthest, max_distance) = line[1..line.len() - 1]
+        .iter()
+        .enumerate()
+        .map(|(index, point)| (index + 1, distance(point)))
+        .fold(
+            (0, 0.0),
+            |best, current| if current.1 > best.1 { current } else { best },
+        );
+
+    if max_distance <= SIMPLIFY_TOLERANCE {
+        vec![first, last]
+    } else {
+        let mut simplified = simplify(&line[..=farthest]);
+        simplified.pop();
+        simplified.extend(simplify(&line[farthest..]));
+        simplified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn rgb(color: &Color) -> Rgb<u8> {
+        Rgb([color.r, color.g, color.b])
+    }
+
+    // one character per pixel: # black, r red, anything else white
+    fn image(rows: &[&str]) -> Image {
+        ImageBuffer::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            match rows[y as usize].as_bytes()[x as usize] {
+                b'#' => rgb(&BLACK),
+                b'r' => rgb(&LIGHT_RED),
+                _ => rgb(&WHITE),
+            }
+        })
+    }
+
+    fn mask(rows: &[&str]) -> Vec<bool> {
+        rows.iter()
+            .flat_map(|row| row.bytes().map(|pixel| pixel == b'#'))
+            .collect()
+    }
+
+    fn show(mask: &[bool], width: usize) -> Vec<String> {
+        mask.chunks(width)
+            .map(|row| row.iter().map(|&set| if set { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn flat_images_have_no_edges() {
+        let image = ImageBuffer::from_pixel(6, 6, Rgb([120, 40, 200]));
+
+        let edges = sobel(&image);
+
+        for y in 0..6 {
+            for x in 0..6 {
+                assert_eq!(edges.magnitude(x, y), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn finds_steps_in_brightness() {
+        let image = ImageBuffer::from_fn(10, 4, |x, _| {
+            if x < 5 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+
+        let edges = sobel(&image);
+
+        // strongest at the step, fading with the blur
+        assert!(edges.magnitude(4, 2) > 60.0);
+        assert!(edges.magnitude(5, 2) > 60.0);
+        assert!(edges.magnitude(3, 2) < edges.magnitude(4, 2));
+        assert!(edges.magnitude(0, 2) < 1.0);
+        assert!(edges.magnitude(9, 0) < 1.0);
+    }
+
+    #[test]
+    fn thins_thick_lines_to_their_middle() {
+        let mut lines = mask(&[
+            "..........",
+            ".########.",
+            ".########.",
+            ".########.",
+            "..........",
+        ]);
+
+        thin(&mut lines, 10, 5);
+
+        assert_eq!(
+            show(&lines, 10),
+            vec![
+                "..........",
+                "..........",
+                "..#####...",
+                "..........",
+                ".........."
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_specks() {
+        let mut lines = mask(&["##......", "........", "..#####.", "........"]);
+
+        remove_specks(&mut lines, 8, 4);
+
+        assert_eq!(
+            show(&lines, 8),
+            vec!["........", "........", "..#####.", "........"]
+        );
+    }
+
+    #[test]
+    fn sketches_outlines_of_shapes() {
+        let shape = ImageBuffer::from_fn(20, 20, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+
+        let lines = lines(&shape);
+
+        // a closed line around the square, nothing in its middle or far outside
+        assert!(!lines[10 * 20 + 10]);
+        assert!(!lines[10 * 20 + 1]);
+        for &(x, y) in [(5, 10), (10, 5), (14, 10), (10, 14)].iter() {
+            let near = (y - 1..=y + 1)
+                .any(|y: u32| (x - 1..=x + 1).any(|x: u32| lines[(y * 20 + x) as usize]));
+            assert!(near, "no line near {} {}", x, y);
+        }
+        // one pixel wide
+        for y in 0..20 {
+            let row = &lines[y * 20..(y + 1) * 20];
+            assert!(
+                row.windows(3).all(|window| window != [true, true, true]) || !(6..=13).contains(&y)
+            );
+        }
+    }
+
+    #[test]
+    fn traces_lines_from_their_ends() {
+        let image = image(&["#####...", "....#...", "....#..r", "....#..r"]);
+
+        let lines = trace(&image, |x, y| *image.get_pixel(x, y) != rgb(&WHITE));
+
+        assert_eq!(
+            lines,
+            vec![vec![(0, 0), (4, 0), (4, 3)], vec![(7, 2), (7, 3)]]
+        );
+    }
+
+    #[test]
+    fn keeps_corners_of_diagonal_lines() {
+        let image = image(&["#.......", ".##.....", "...##...", ".....###"]);
+
+        let lines = trace(&image, |x, y| *image.get_pixel(x, y) != rgb(&WHITE));
+
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].first(), Some(&(0, 0)));
+        assert_eq!(lines[0].last(), Some(&(7, 3)));
+        assert!(lines[0].len() <= 3);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{
+    colors::{Color, WHITE},
+    image_converter::Image,
+};
+
+/// Regions with fewer pixels are drawn pixel by pixel
+pub const MIN_REGION_SIZE: usize = 50;
+
+/// Connected pixels of the same color
+#[derive(PartialEq, Eq, Debug)]
+pub struct Region {
+    pub color: Color,
+    pub pixels: Vec<(u32, u32)>,
+}
+
+/// What has to be drawn to fill the large regions of an image with the bucket
+pub struct FillPlan {
+    width: u32,
+    outline: Vec<bool>,
+    filled: Vec<bool>,
+    /// One pixel of every area the bucket has to be clicked in
+    pub seeds: Vec<(u32, u32, Color)>,
+}
+
+impl FillPlan {
+    /// Pixels that have to be drawn before filling, so the bucket doesn't leak
+    pub fn is_outline(&self, x: u32, y: u32) -> bool {
+        self.outline[(y * self.width + x) as usize]
+    }
+
+    /// Pixels that are covered by the outlines and fills
+    pub fn is_filled(&self, x: u32, y: u32) -> bool {
+        self.filled[(y * self.width + x) as usize]
+    }
+}
+
+fn color_at(image: &Image, x: u32, y: u32) -> Color {
+    let pixel = image.get_pixel(x, y);
+
+    Color {
+        r: pixel[0],
+        g: pixel[1],
+        b: pixel[2],
+    }
+}
+
+// pixels next to a pixel, without the ones outside of the image
+fn neighbours(x: u32, y: u32, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let neighbours = [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ];
+
+    IntoIterator::into_iter(neighbours).filter(move |&(x, y)| x < width && y < height)
+}
+
+// 4-connected components of the pixels that match, the game fills the same way
+fn components<F>(width: u32, height: u32, connected: F) -> Vec<Vec<(u32, u32)>>
+where
+    F: Fn((u32, u32), (u32, u32)) -> bool,
+{
+    let mut visited = vec![false; (width * height) as usize];
+    let mut components = Vec::new();
+
+    for y in 0..height {
+        for x in 0..width {
+            if visited[(y * width + x) as usize] || !connected((x, y), (x, y)) {
+                continue;
+            }
+
+            let mut component = Vec::new();
+            let mut stack = vec![(x, y)];
+            visited[(y * width + x) as usize] = true;
+
+            while let Some(pixel) = stack.pop() {
+                component.push(pixel);
+
+                for neighbour in neighbours(pixel.0, pixel.1, width, height) {
+                    let index = (neighbour.1 * width + neighbour.0) as usize;
+
+                    if !visited[index] && connected(pixel, neighbour) {
+                        visited[index] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+
+            component.sort_by_key(|&(x, y)| (y, x));
+            components.push(component);
+        }
+    }
+
+    components
+}
+
+/// Splits the image into regions of connected pixels with the same color
+pub fn segment(image: &Image) -> Vec<Region> {
+    components(image.width(), image.height(), |a, b| {
+        image.get_pixel(a.0, a.1) == image.get_pixel(b.0, b.1)
+    })
+    .into_iter()
+    .map(|pixels| Region {
+        color: color_at(image, pixels[0].0, pixels[0].1),
+        pixels,
+    })
+    .collect()
+}
+
+/// Plans which regions get outlined and filled, regions smaller than
+/// `min_size` pixels and white ones are left for the brush
+pub fn plan(image: &Image, min_size: usize) -> FillPlan {
+    let (width, height) = image.dimensions();
+    let mut plan = FillPlan {
+        width,
+        outline: vec![false; (width * height) as usize],
+        filled: vec![false; (width * height) as usize],
+        seeds: Vec::new(),
+    };
+
+    for region in segment(image) {
+        if region.color == WHITE || region.pixels.len() < min_size {
+            continue;
+        }
+
+        // every pixel next to another color or the edge of the image is part of the outline,
+        // the edge might not be the edge of the canvas
+        let mut inside = vec![false; (width * height) as usize];
+        for &(x, y) in region.pixels.iter() {
+            let on_edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+            let next_to_other = neighbours(x, y, width, height)
+                .any(|(nx, ny)| color_at(image, nx, ny) != region.color);
+
+            if on_edge || next_to_other {
+                plan.outline[(y * width + x) as usize] = true;
+            } else {
+                inside[(y * width + x) as usize] = true;
+            }
+        }
+
+        // thin parts of the outline can split the inside into several areas
+        let areas = components(width, height, |a, b| {
+            inside[(a.1 * width + a.0) as usize] && inside[(b.1 * width + b.0) as usize]
+        });
+
+        // without an inside filling doesn't save anything
+        if areas.is_empty() {
+            for &(x, y) in region.pixels.iter() {
+                plan.outline[(y * width + x) as usize] = false;
+            }
+            continue;
+        }
+
+        for area in areas {
+            let (x, y) = area[0];
+            plan.seeds.push((x, y, region.color.clone()));
+        }
+        for &(x, y) in region.pixels.iter() {
+            plan.filled[(y * width + x) as usize] = true;
+        }
+    }
+
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::*;
+    use image::{ImageBuffer, Rgb};
+
+    // one character per pixel: . white, b black, r red
+    fn image(rows: &[&str]) -> Image {
+        ImageBuffer::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            let color = match rows[y as usize].as_bytes()[x as usize] {
+                b'b' => BLACK,
+                b'r' => LIGHT_RED,
+                _ => WHITE,
+            };
+
+            Rgb([color.r, color.g, color.b])
+        })
+    }
+
+    fn outline(plan: &FillPlan, image: &Image) -> Vec<String> {
+        (0..image.height())
+            .map(|y| {
+                (0..image.width())
+                    .map(|x| match (plan.is_outline(x, y), plan.is_filled(x, y)) {
+                        (true, _) => 'o',
+                        (false, true) => 'f',
+                        (false, false) => '.',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn segments_connected_colors() {
+        let image = image(&["bb.r", "b..r", "..b.", "rr.."]);
+
+        let regions = segment(&image);
+        let summary: Vec<(Color, usize)> = regions
+            .iter()
+            .map(|region| (region.color.clone(), region.pixels.len()))
+            .collect();
+
+        // diagonal pixels are not connected, the black pixel splits the white
+        assert_eq!(
+            summary,
+            vec![
+                (BLACK, 3),
+                (WHITE, 5),
+                (LIGHT_RED, 2),
+                (BLACK, 1),
+                (WHITE, 3),
+                (LIGHT_RED, 2),
+            ]
+        );
+        assert_eq!(regions[0].pixels, vec![(0, 0), (1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn outlines_and_fills_large_regions() {
+        let image = image(&["......", ".bbbb.", ".bbbb.", ".bbbb.", "......", "rr...."]);
+
+        let plan = plan(&image, 9);
+
+        assert_eq!(
+            outline(&plan, &image),
+            vec!["......", ".oooo.", ".offo.", ".oooo.", "......", "......"]
+        );
+        assert_eq!(plan.seeds, vec![(2, 2, BLACK)]);
+    }
+
+    #[test]
+    fn edges_of_the_image_are_outlined() {
+        let image = image(&["bbbb", "bbbb", "bbbb"]);
+
+        let plan = plan(&image, 1);
+
+        assert_eq!(outline(&plan, &image), vec!["oooo", "offo", "oooo"]);
+    }
+
+    #[test]
+    fn holes_are_not_filled() {
+        let image = image(&[
+            "bbbbbbb", "bbbbbbb", "bbbbbbb", "bbbrbbb", "bbbbbbb", "bbbbbbb", "bbbbbbb",
+        ]);
+
+        let plan = plan(&image, 1);
+
+        assert_eq!(
+            outline(&plan, &image),
+            vec!["ooooooo", "offfffo", "offoffo", "ofo.ofo", "offoffo", "offfffo", "ooooooo",]
+        );
+        // the red pixel is too small and is left for the brush
+        assert_eq!(plan.seeds, vec![(1, 1, BLACK)]);
+        assert!(!plan.is_filled(3, 3));
+    }
+
+    #[test]
+    fn thin_parts_split_the_inside() {
+        let image = image(&["bbb.bbb", "bbbbbbb", "bbb.bbb"]);
+
+        let plan = plan(&image, 1);
+
+        assert_eq!(
+            outline(&plan, &image),
+            vec!["ooo.ooo", "offoffo", "ooo.ooo"]
+        );
+        assert_eq!(plan.seeds, vec![(1, 1, BLACK), (4, 1, BLACK)]);
+    }
+
+    #[test]
+    fn small_and_white_regions_are_left_for_the_brush() {
+        let image = image(&["bbb...", "bbb...", "bbb..."]);
+
+        let plan = plan(&image, 10);
+
+        assert!(plan.seeds.is_empty());
+        assert_eq!(outline(&plan, &image), vec!["......"; 3]);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    brushes::Brush,
+    drawer::{Box, ColorPicker, Tools},
+    palette::Palette,
+};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Where a button is in screen pixels, from the top left corner of the first swatch
+type Offset = (i32, i32);
+
+/// Ids of the games whose profiles come with the bot, skribbl.io first
+pub const BUILTIN: [&str; 4] = ["skribbl", "garticphone", "drawize", "pinturillo"];
+
+/// Colors, brush sizes and buttons of a game, measured at 100% zoom.
+/// The profiles of other games than the built-in ones are read from a JSON file like
+/// `{"palette": {"name": "mine", "rows": [["#ffffff", "#000000"]]}, "swatch": [36, 36],
+/// "brushes": [{"offset": [18, 300], "size": 4}],
+/// "tools": {"brush": [18, -60], "bucket": [90, -60]},
+/// "picker": {"button": [54, 236], "entry": [54, 392]}}`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Profile {
+    pub palette: Palette,
+    // size of a swatch, the other positions scale with it
+    swatch: (u32, u32),
+    // brush size buttons and the brush diameters
+    #[serde(default)]
+    brushes: Vec<BrushButton>,
+    // None if they have to be entered
+    #[serde(default)]
+    tools: Option<ToolButtons>,
+    // None if colors are picked by clicking their swatch
+    #[serde(default)]
+    picker: Option<PickerButtons>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct BrushButton {
+    offset: Offset,
+    size: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct ToolButtons {
+    brush: Offset,
+    bucket: Offset,
+}
+
+// the button that opens the color picker and its hex entry
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+struct PickerButtons {
+    button: Offset,
+    entry: Offset,
+}
+
+impl Profile {
+    /// Only the palette is known, the tools and brushes have to be entered
+    pub fn skribbl() -> Profile {
+        Profile {
+            palette: Palette::skribbl(),
+            swatch: (24, 24),
+            brushes: Vec::new(),
+            tools: None,
+            picker: None,
+        }
+    }
+
+    /// A profile that comes with the bot, the files are in `profiles/`
+    pub fn builtin(id: &str) -> Option<Profile> {
+        let json = match id {
+            "skribbl" => return Some(Profile::skribbl()),
+            "garticphone" => include_str!("../profiles/garticphone.json"),
+            "drawize" => include_str!("../profiles/drawize.json"),
+            "pinturillo" => include_str!("../profiles/pinturillo.json"),
+            _ => return None,
+        };
+
+        Some(serde_json::from_str(json).expect("Invalid built-in profile"))
+    }
+
+    /// The built-in profile with that id, otherwise the profile file at that path
+    pub fn find(name: &str) -> Result<Profile, String> {
+        match Profile::builtin(name) {
+            Some(profile) => Ok(profile),
+            None => Profile::load(name),
+        }
+    }
+
+    pub fn load(path: &str) -> Result<Profile, String> {
+        let content =
+            fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let profile: Profile = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to read {}: {}", path, err))?;
+
+        profile
+            .check()
+            .map_err(|err| format!("Profile {}: {}", path, err))?;
+        Ok(profile)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if self.swatch.0 == 0 || self.swatch.1 == 0 {
+            return Err("The swatch has no size".into());
+        }
+
+        self.palette.check()
+    }
+
+    /// Brush size buttons for a color box of that size, empty if they have to be entered
+    pub fn brushes(&self, color_box: Box) -> Vec<Brush> {
+        self.brushes
+            .iter()
+            .map(|button| {
+                let (x, y) = self.place(color_box, button.offset);
+                let size =
+                    ((button.size * color_box.width + self.swatch.0 / 2) / self.swatch.0).max(1);
+
+                Brush { x, y, size }
+            })
+            .collect()
+    }
+
+    pub fn tools(&self, color_box: Box) -> Option<Tools> {
+        self.tools.map(|tools| Tools {
+            brush: self.place(color_box, tools.brush),
+            bucket: self.place(color_box, tools.bucket),
+        })
+    }
+
+    /// The color picker, None if the game only has swatches
+    pub fn color_picker(&self, color_box: Box) -> Option<ColorPicker> {
+        self.picker.map(|picker| ColorPicker {
+            button: self.place(color_box, picker.button),
+            entry: self.place(color_box, picker.entry),
+        })
+    }
+
+    // screen position of an offset, scaled by the zoom the color box was measured at
+    fn place(&self, color_box: Box, (x, y): Offset) -> (u32, u32) {
+        let scale = |offset: i32, size: u32, swatch: u32| {
+            (i64::from(offset) * i64::from(size) / i64::from(swatch)) as i32
+        };
+
+        (
+            (color_box.x as i32 + scale(x, color_box.width, self.swatch.0)).max(0) as u32,
+            (color_box.y as i32 + scale(y, color_box.height, self.swatch.1)).max(0) as u32,
+        )
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::colors::{BLACK, WHITE};
+
+    // a made up game with a color picker, not the layout of a real one
+    pub const PICKER_PROFILE: &str = r##"{
+        "palette": {"name": "test", "rows": [["#ffffff", "#000000"]]},
+        "swatch": [36, 36],
+        "brushes": [{"offset": [18, 300], "size": 4}, {"offset": [72, 340], "size": 40}],
+        "tools": {"brush": [18, -60], "bucket": [90, -60]},
+        "picker": {"button": [54, 236], "entry": [54, 392]}
+    }"##;
+
+    /// Writes the profile to a temporary file for the settings
+    pub fn profile_file(name: &str, json: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "skribbl_profile_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        fs::write(&path, json).unwrap();
+
+        path.to_str().unwrap().into()
+    }
+
+    #[test]
+    fn reads_profiles_from_json() {
+        let profile: Profile = serde_json::from_str(PICKER_PROFILE).unwrap();
+        assert_eq!(profile.palette.rows, vec![vec![WHITE, BLACK]]);
+        assert!(profile.check().is_ok());
+
+        // games without tools or a picker only need the palette and the swatch size
+        let json = r##"{"palette": {"name": "t", "rows": [["#ffffff"]]}, "swatch": [0, 20]}"##;
+        let profile: Profile = serde_json::from_str(json).unwrap();
+        assert!(profile.brushes.is_empty() && profile.tools.is_none());
+        assert!(profile.check().is_err());
+    }
+
+    #[test]
+    fn built_in_profiles_are_usable() {
+        for id in BUILTIN.iter() {
+            let profile = Profile::builtin(id).unwrap();
+
+            assert!(profile.check().is_ok(), "{}", id);
+            // calibration looks for the swatches in the first row
+            assert!(profile.palette.get(2, 0).is_some(), "{}", id);
+        }
+
+        // Gartic Phone takes any color in its picker
+        let gartic = Profile::find("garticphone").unwrap();
+        assert_eq!(gartic.palette.name, "Gartic Phone");
+        assert!(gartic.picker.is_some());
+        assert!(Profile::builtin("drawize").unwrap().picker.is_none());
+        assert!(Profile::find("no such game").is_err());
+    }
+
+    #[test]
+    fn scales_positions_with_the_swatches() {
+        let profile: Profile = serde_json::from_str(PICKER_PROFILE).unwrap();
+        let color_box = |size| Box {
+            x: 100,
+            y: 500,
+            width: size,
+            height: size,
+        };
+
+        let tools = profile.tools(color_box(36)).unwrap();
+        assert_eq!(tools.brush, (118, 440));
+        assert_eq!(tools.bucket, (190, 440));
+
+        // zoomed out to half the size
+        let picker = profile.color_picker(color_box(18)).unwrap();
+        assert_eq!(picker.button, (127, 618));
+        assert_eq!(picker.entry, (127, 696));
+        assert_eq!(profile.brushes(color_box(18))[1].size, 20);
+
+        assert!(Profile::skribbl().tools(color_box(24)).is_none());
+        assert!(Profile::skribbl().brushes(color_box(24)).is_empty());
+    }
+}
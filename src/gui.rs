@@ -1,22 +1,26 @@
 use crate::{
+    budget, calibration,
     desktop::{ClickType, Desktop},
-    drawer::{Box, Drawer},
+    drawer::{DrawEvent, Drawer},
+    hotkeys::{self, Action},
     image_converter,
     image_downloader::{self, DownloadImageError, ImageDownloader},
+    session::{Recorder, Session},
     settings::Settings,
 };
-use gdk_pixbuf::Pixbuf;
+use gdk_pixbuf::{Colorspace, Pixbuf};
 use gio::prelude::*;
 use glib::{MainContext, Receiver, Sender};
 use gtk::{
-    prelude::*, Application, ApplicationWindow, Builder, Button, CheckButton, Entry, IconView,
-    Label, ListStore, Scale, SearchEntry,
+    prelude::*, Application, ApplicationWindow, Builder, Button, CheckButton, ComboBoxText, Entry,
+    IconView, Label, ListStore, ProgressBar, Scale, SearchEntry,
 };
-use image::DynamicImage;
+use image::{imageops::FilterType, DynamicImage};
 use std::{
     io,
+    rc::Rc,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc, RwLock,
     },
     thread,
@@ -24,12 +28,22 @@ use std::{
 };
 use uuid::Uuid;
 
+const DRY_RUN_FILENAME: &str = "dry_run.png";
+// the preview is scaled to fit into a square of this size
+const PREVIEW_SIZE: u32 = 320;
+
 #[derive(Clone)]
 enum Instruction {
     UpdateSettings,
+    ShowSettings,
     UpdateStatus(String),
+    ShowProgress(f64, String),
+    ShowPreview(image_converter::Image, String),
+    ClearPreview,
     NewImages(Uuid),
     AddImage(Uuid, Vec<u8>),
+    DrawClipboard,
+    NextResult,
 }
 
 #[derive(Clone)]
@@ -81,35 +95,67 @@ pub struct GTK {
     pub color_y: Entry,
     pub color_width: Entry,
     pub color_height: Entry,
-    pub dither: CheckButton,
+    pub brush_tool_x: Entry,
+    pub brush_tool_y: Entry,
+    pub fill_tool_x: Entry,
+    pub fill_tool_y: Entry,
+    pub brushes: Entry,
+    pub hotkey_draw_clipboard: Entry,
+    pub hotkey_pause: Entry,
+    pub hotkey_resume: Entry,
+    pub hotkey_abort: Entry,
+    pub hotkey_next_result: Entry,
+    pub dithering: ComboBoxText,
+    pub color_distance: ComboBoxText,
+    pub sketch: ComboBoxText,
     pub checkerboard: CheckButton,
+    pub fill: CheckButton,
+    pub optimize: CheckButton,
+    pub verify: CheckButton,
     pub grayscale: CheckButton,
+    pub strokes: ComboBoxText,
+    pub order: ComboBoxText,
+    pub pointer_guard: ComboBoxText,
+    pub game: ComboBoxText,
     pub delay: Scale,
     pub scale: Scale,
     pub step: Scale,
+    pub time_budget: Scale,
+    pub custom_colors: Scale,
+    pub max_colors: Scale,
     pub search: SearchEntry,
     pub images_view: IconView,
     pub images_store: ListStore,
+    pub preview: gtk::Image,
+    pub preview_info: Label,
+    pub progress: ProgressBar,
     pub status: Label,
     pub draw: Button,
+    pub resume: Button,
     pub save: Button,
+    pub calibrate: Button,
+    pub dry_run: Button,
 }
 
 pub struct GUI {
     sender: Sender<Message>,
     uuid_list: Arc<RwLock<Vec<Uuid>>>,
-    desktop: Arc<Desktop>,
+    desktop: Arc<dyn Desktop>,
     drawer_running: Arc<AtomicBool>,
+    drawer_paused: Arc<AtomicBool>,
     settings: Arc<RwLock<Settings>>,
     images_list: Arc<RwLock<Vec<DynamicImage>>>,
+    // only the newest preview is shown
+    preview_generation: Arc<AtomicUsize>,
     gtk: GTK,
 }
 
 impl GUI {
     pub fn new(
         settings: io::Result<Settings>,
-        desktop: Desktop,
+        desktop: Arc<dyn Desktop>,
         drawer_running: Arc<AtomicBool>,
+        drawer_paused: Arc<AtomicBool>,
     ) -> GUI {
         let (sender, receiver) = MainContext::channel(glib::PRIORITY_DEFAULT);
         let application = gtk::Application::new(Some("friz64.skribbliobot"), Default::default())
@@ -126,6 +172,27 @@ impl GUI {
                 Settings::default()
             }
         };
+        let hotkeys = hotkeys::start(settings.hotkeys(), drawer_running.clone(), {
+            let sender = sender.clone();
+            let drawer_running = drawer_running.clone();
+            let drawer_paused = drawer_paused.clone();
+
+            move |action| match action {
+                Action::DrawClipboard if !drawer_running.load(Ordering::Relaxed) => {
+                    Message::send(sender.clone(), Instruction::DrawClipboard)
+                }
+                Action::NextResult => Message::send(sender.clone(), Instruction::NextResult),
+                _ => hotkeys::control_drawing(action, &drawer_running, &drawer_paused),
+            }
+        });
+        let hotkeys = match hotkeys {
+            Ok(hotkeys) => Some(hotkeys),
+            Err(err) => {
+                println!("{}", err);
+                None
+            }
+        };
+
         let settings = Arc::new(RwLock::new(settings));
         let images_list = Arc::new(RwLock::new(Vec::new()));
         let uuid_list = Arc::new(RwLock::new(Vec::new()));
@@ -146,18 +213,46 @@ impl GUI {
             color_y: builder.get_object("ColorY").unwrap(),
             color_width: builder.get_object("ColorWidth").unwrap(),
             color_height: builder.get_object("ColorHeight").unwrap(),
-            dither: builder.get_object("Dither").unwrap(),
+            brush_tool_x: builder.get_object("BrushToolX").unwrap(),
+            brush_tool_y: builder.get_object("BrushToolY").unwrap(),
+            fill_tool_x: builder.get_object("FillToolX").unwrap(),
+            fill_tool_y: builder.get_object("FillToolY").unwrap(),
+            brushes: builder.get_object("Brushes").unwrap(),
+            hotkey_draw_clipboard: builder.get_object("HotkeyDrawClipboard").unwrap(),
+            hotkey_pause: builder.get_object("HotkeyPause").unwrap(),
+            hotkey_resume: builder.get_object("HotkeyResume").unwrap(),
+            hotkey_abort: builder.get_object("HotkeyAbort").unwrap(),
+            hotkey_next_result: builder.get_object("HotkeyNextResult").unwrap(),
+            dithering: builder.get_object("Dithering").unwrap(),
+            color_distance: builder.get_object("ColorDistance").unwrap(),
+            sketch: builder.get_object("Sketch").unwrap(),
             checkerboard: builder.get_object("Checkerboard").unwrap(),
+            fill: builder.get_object("Fill").unwrap(),
+            optimize: builder.get_object("Optimize").unwrap(),
+            verify: builder.get_object("Verify").unwrap(),
             grayscale: builder.get_object("Grayscale").unwrap(),
+            strokes: builder.get_object("Strokes").unwrap(),
+            order: builder.get_object("Order").unwrap(),
+            pointer_guard: builder.get_object("PointerGuard").unwrap(),
+            game: builder.get_object("Game").unwrap(),
             delay: builder.get_object("Delay").unwrap(),
             scale: builder.get_object("Scale").unwrap(),
             step: builder.get_object("Step").unwrap(),
+            time_budget: builder.get_object("TimeBudget").unwrap(),
+            custom_colors: builder.get_object("CustomColors").unwrap(),
+            max_colors: builder.get_object("MaxColors").unwrap(),
             search: builder.get_object("Search").unwrap(),
             images_view,
             images_store,
+            preview: builder.get_object("Preview").unwrap(),
+            preview_info: builder.get_object("PreviewInfo").unwrap(),
+            progress: builder.get_object("Progress").unwrap(),
             status,
             draw: builder.get_object("Draw").unwrap(),
+            resume: builder.get_object("Resume").unwrap(),
             save: builder.get_object("Save").unwrap(),
+            calibrate: builder.get_object("Calibrate").unwrap(),
+            dry_run: builder.get_object("DryRun").unwrap(),
         };
 
         GUI::set_receiver(
@@ -165,16 +260,19 @@ impl GUI {
             settings.clone(),
             images_list.clone(),
             uuid_list.clone(),
+            hotkeys,
             gtk.clone(),
         );
 
         GUI {
             sender,
             uuid_list,
-            desktop: Arc::new(desktop),
+            desktop,
             drawer_running,
+            drawer_paused,
             settings,
             images_list,
+            preview_generation: Arc::new(AtomicUsize::new(0)),
             gtk,
         }
     }
@@ -208,6 +306,7 @@ impl GUI {
             let gtk = self.gtk.clone();
             let settings = self.settings.clone();
             let drawer_running = self.drawer_running.clone();
+            let drawer_paused = self.drawer_paused.clone();
             let images_list = self.images_list.clone();
             let desktop = self.desktop.clone();
             let sender = self.sender.clone();
@@ -217,89 +316,200 @@ impl GUI {
                 let gtk = gtk.clone();
                 let settings = settings.clone();
                 let drawer_running = drawer_running.clone();
+                let drawer_paused = drawer_paused.clone();
                 let images_list = images_list.clone();
                 let desktop = desktop.clone();
                 let sender = sender.clone();
                 let uuid_list = uuid_list.clone();
 
-                let image = gtk
-                    .images_view
-                    .get_selected_items()
-                    .get(0)
-                    .map(|tree_path| {
-                        let index = images_list.read().unwrap()
-                            [tree_path.get_indices()[0] as usize]
-                            .clone();
-                        Some(index)
-                    });
+                let image = GUI::selected_image(&gtk, &images_list);
 
                 thread::spawn(move || {
                     Message::send_waiting(sender.clone(), uuid_list, Instruction::UpdateSettings);
 
-                    let settings = settings.read().unwrap();
-                    if GUI::is_ready(&settings) {
-                        let drawing_area = Box {
-                            x: settings.drawing_x,
-                            y: settings.drawing_y,
-                            width: settings.drawing_width,
-                            height: settings.drawing_height,
-                        };
-
-                        let color_box = Box {
-                            x: settings.color_x,
-                            y: settings.color_y,
-                            width: settings.color_width,
-                            height: settings.color_height,
-                        };
-
-                        let image = image.unwrap_or_else(|| {
-                            match image_converter::image_from_clipboard() {
-                                Ok(image) => Some(image),
-                                Err(err) => {
-                                    Message::send(sender.clone(), Instruction::UpdateStatus(err));
-                                    None
-                                }
-                            }
-                        });
-
-                        let mut drawer = Drawer::new(
-                            drawing_area,
-                            color_box,
-                            settings.checkerboard,
-                            settings.delay as u64,
-                            settings.step.unwrap(),
-                        );
+                    let settings = settings.read().unwrap().clone();
+                    if settings.is_ready() {
+                        let image = image.or_else(|| GUI::image_from_clipboard(sender.clone()));
 
                         if let Some(image) = image {
-                            let converted = image_converter::convert(
-                                image,
-                                settings.dither,
-                                settings.grayscale.unwrap_or(false),
-                                settings.scale,
-                                settings.drawing_width,
-                                settings.drawing_height,
+                            let fit = budget::fit(&image, &settings);
+                            let help = settings.hotkeys().drawing_help();
+                            let status = if fit.lowered.is_empty() {
+                                format!("Drawing - {}", help)
+                            } else {
+                                format!("Drawing with {} - {}", fit.lowered.join(", "), help)
+                            };
+
+                            GUI::draw_session(
+                                &settings,
+                                Session::new(&fit.settings, &fit.image),
+                                status,
+                                &*desktop,
+                                &drawer_running,
+                                &drawer_paused,
+                                sender.clone(),
                             );
+                        }
+                    } else {
+                        Message::send(
+                            sender.clone(),
+                            Instruction::UpdateStatus("Please enter positions".into()),
+                        );
+                    }
+                });
+            }
+        });
 
+        self.gtk.resume.connect_clicked({
+            let settings = self.settings.clone();
+            let drawer_running = self.drawer_running.clone();
+            let drawer_paused = self.drawer_paused.clone();
+            let desktop = self.desktop.clone();
+            let sender = self.sender.clone();
+            let uuid_list = self.uuid_list.clone();
+
+            move |_| {
+                let settings = settings.clone();
+                let drawer_running = drawer_running.clone();
+                let drawer_paused = drawer_paused.clone();
+                let desktop = desktop.clone();
+                let sender = sender.clone();
+                let uuid_list = uuid_list.clone();
+
+                thread::spawn(move || {
+                    Message::send_waiting(sender.clone(), uuid_list, Instruction::UpdateSettings);
+
+                    let session = match Session::load() {
+                        Ok(Some(session)) => session,
+                        Ok(None) => {
                             Message::send(
                                 sender.clone(),
-                                Instruction::UpdateStatus("Drawing - Cancel with ESC".into()),
+                                Instruction::UpdateStatus("There is no drawing to continue".into()),
                             );
+                            return;
+                        }
+                        Err(err) => {
+                            Message::send(sender.clone(), Instruction::UpdateStatus(err));
+                            return;
+                        }
+                    };
 
-                            // focus the drawing window before drawing
-                            desktop.move_cursor(settings.color_x, settings.color_y);
-                            desktop.left_click(ClickType::Once);
+                    let settings = settings.read().unwrap().clone();
+                    let status = format!(
+                        "Continuing after {} steps - {}",
+                        session.done,
+                        settings.hotkeys().drawing_help()
+                    );
+                    GUI::draw_session(
+                        &settings,
+                        session,
+                        status,
+                        &*desktop,
+                        &drawer_running,
+                        &drawer_paused,
+                        sender.clone(),
+                    );
+                });
+            }
+        });
 
-                            drawer_running.store(true, Ordering::Relaxed);
-                            drawer.draw(&desktop, &converted, drawer_running.clone());
-                            drawer_running.store(false, Ordering::Relaxed);
+        self.gtk.dry_run.connect_clicked({
+            let gtk = self.gtk.clone();
+            let settings = self.settings.clone();
+            let images_list = self.images_list.clone();
+            let sender = self.sender.clone();
+            let uuid_list = self.uuid_list.clone();
 
-                            Message::send(sender.clone(), Instruction::UpdateStatus("Idle".into()));
-                        }
-                    } else {
+            move |_| {
+                let settings = settings.clone();
+                let sender = sender.clone();
+                let uuid_list = uuid_list.clone();
+
+                let image = GUI::selected_image(&gtk, &images_list);
+
+                thread::spawn(move || {
+                    Message::send_waiting(sender.clone(), uuid_list, Instruction::UpdateSettings);
+
+                    let settings = settings.read().unwrap().clone();
+                    if !settings.is_ready() {
                         Message::send(
                             sender.clone(),
                             Instruction::UpdateStatus("Please enter positions".into()),
                         );
+                        return;
+                    }
+                    if let Err(err) = settings.check_files() {
+                        Message::send(sender.clone(), Instruction::UpdateStatus(err));
+                        return;
+                    }
+
+                    if let Some(image) = image.or_else(|| GUI::image_from_clipboard(sender.clone()))
+                    {
+                        let fit = budget::fit(&image, &settings);
+
+                        let status = match fit.canvas.save(DRY_RUN_FILENAME) {
+                            Ok(()) => format!("Dry run saved to {} - {}", DRY_RUN_FILENAME, fit),
+                            Err(err) => format!("Failed to write {}: {}", DRY_RUN_FILENAME, err),
+                        };
+                        Message::send(sender.clone(), Instruction::UpdateStatus(status));
+                    }
+                });
+            }
+        });
+
+        self.gtk.calibrate.connect_clicked({
+            let settings = self.settings.clone();
+            let desktop = self.desktop.clone();
+            let sender = self.sender.clone();
+            let uuid_list = self.uuid_list.clone();
+
+            move |_| {
+                let settings = settings.clone();
+                let desktop = desktop.clone();
+                let sender = sender.clone();
+                let uuid_list = uuid_list.clone();
+
+                thread::spawn(move || {
+                    Message::send_waiting(
+                        sender.clone(),
+                        uuid_list.clone(),
+                        Instruction::UpdateSettings,
+                    );
+
+                    // give the user time to bring the game to the front
+                    for seconds in (1..=3).rev() {
+                        Message::send(
+                            sender.clone(),
+                            Instruction::UpdateStatus(format!(
+                                "Calibrating in {} - Show the game with an empty canvas",
+                                seconds
+                            )),
+                        );
+                        thread::sleep(Duration::from_secs(1));
+                    }
+
+                    let calibration = desktop.capture_screen().and_then(|screenshot| {
+                        let palette = settings.read().unwrap().palette()?;
+                        calibration::calibrate(&screenshot, &palette)
+                    });
+
+                    match calibration {
+                        Ok(calibration) => {
+                            calibration.apply(&mut settings.write().unwrap());
+
+                            Message::send_waiting(
+                                sender.clone(),
+                                uuid_list,
+                                Instruction::ShowSettings,
+                            );
+                            Message::send(
+                                sender.clone(),
+                                Instruction::UpdateStatus(
+                                    "Calibrated - Save Settings to keep the positions".into(),
+                                ),
+                            );
+                        }
+                        Err(err) => Message::send(sender.clone(), Instruction::UpdateStatus(err)),
                     }
                 });
             }
@@ -316,6 +526,48 @@ impl GUI {
             .scale
             .connect_format_value(|_, val| format!("{}%", (val * 100.0).round()));
 
+        let update_preview = self.preview_updater();
+        for check_button in &[
+            &self.gtk.grayscale,
+            &self.gtk.checkerboard,
+            &self.gtk.fill,
+            &self.gtk.optimize,
+            &self.gtk.verify,
+        ] {
+            check_button.connect_toggled({
+                let update_preview = update_preview.clone();
+                move |_| update_preview()
+            });
+        }
+        for scale in &[
+            &self.gtk.scale,
+            &self.gtk.step,
+            &self.gtk.time_budget,
+            &self.gtk.custom_colors,
+            &self.gtk.max_colors,
+        ] {
+            scale.connect_value_changed({
+                let update_preview = update_preview.clone();
+                move |_| update_preview()
+            });
+        }
+        for combo_box in &[
+            &self.gtk.dithering,
+            &self.gtk.color_distance,
+            &self.gtk.strokes,
+            &self.gtk.order,
+            &self.gtk.sketch,
+            &self.gtk.game,
+        ] {
+            combo_box.connect_changed({
+                let update_preview = update_preview.clone();
+                move |_| update_preview()
+            });
+        }
+        self.gtk
+            .images_view
+            .connect_selection_changed(move |_| update_preview());
+
         self.gtk.search.connect_activate({
             let images_list = self.images_list.clone();
             let sender = self.sender.clone();
@@ -371,15 +623,175 @@ impl GUI {
         });
     }
 
-    fn is_ready(settings: &Settings) -> bool {
-        settings.drawing_x != 0
-            && settings.drawing_y != 0
-            && settings.drawing_width != 0
-            && settings.drawing_height != 0
-            && settings.color_x != 0
-            && settings.color_y != 0
-            && settings.color_width != 0
-            && settings.color_height != 0
+    // renders the selected image in the background whenever it's called
+    fn preview_updater(&self) -> Rc<dyn Fn()> {
+        let gtk = self.gtk.clone();
+        let settings = self.settings.clone();
+        let images_list = self.images_list.clone();
+        let preview_generation = self.preview_generation.clone();
+        let sender = self.sender.clone();
+        let uuid_list = self.uuid_list.clone();
+
+        Rc::new(move || {
+            let generation = preview_generation.fetch_add(1, Ordering::SeqCst) + 1;
+
+            let image = match GUI::selected_image(&gtk, &images_list) {
+                Some(image) => image,
+                None => {
+                    Message::send(sender.clone(), Instruction::ClearPreview);
+                    return;
+                }
+            };
+
+            let settings = settings.clone();
+            let preview_generation = preview_generation.clone();
+            let sender = sender.clone();
+            let uuid_list = uuid_list.clone();
+
+            thread::spawn(move || {
+                Message::send_waiting(sender.clone(), uuid_list, Instruction::UpdateSettings);
+
+                let is_newest = || preview_generation.load(Ordering::SeqCst) == generation;
+                if !is_newest() {
+                    return;
+                }
+
+                let settings = settings.read().unwrap().clone();
+                if settings.drawing_width == 0 || settings.drawing_height == 0 {
+                    Message::send(sender.clone(), Instruction::ClearPreview);
+                    return;
+                }
+
+                let (preview, info) = if let Err(err) = settings.check_files() {
+                    Message::send(sender, Instruction::UpdateStatus(err));
+                    return;
+                } else if settings.is_ready() {
+                    let fit = budget::fit(&image, &settings);
+                    let info = match fit.restriction(&image) {
+                        Some(restriction) => format!("{}\n{}", fit, restriction),
+                        None => fit.to_string(),
+                    };
+                    (fit.canvas, info)
+                } else {
+                    let converted = image_converter::convert_with_settings(image, &settings);
+                    (converted, "Enter positions to see the drawing".into())
+                };
+
+                let (width, height) = image_converter::resize_dimensions(
+                    preview.width(),
+                    preview.height(),
+                    PREVIEW_SIZE,
+                    PREVIEW_SIZE,
+                    false,
+                );
+                let preview = image::imageops::resize(&preview, width, height, FilterType::Nearest);
+
+                if is_newest() {
+                    Message::send(sender, Instruction::ShowPreview(preview, info));
+                }
+            });
+        })
+    }
+
+    // draws the image of the session, the hotkeys come from `settings`
+    fn draw_session(
+        settings: &Settings,
+        session: Session,
+        status: String,
+        desktop: &dyn Desktop,
+        drawer_running: &Arc<AtomicBool>,
+        drawer_paused: &Arc<AtomicBool>,
+        sender: Sender<Message>,
+    ) {
+        let image = match session.settings.check_files().and_then(|_| session.image()) {
+            Ok(image) => image,
+            Err(err) => {
+                Message::send(sender, Instruction::UpdateStatus(err));
+                return;
+            }
+        };
+
+        let mut drawer = Drawer::from_settings(&session.settings)
+            .with_skip(session.done)
+            .with_pause(drawer_paused.clone());
+        let (color_x, color_y) = (session.settings.color_x, session.settings.color_y);
+
+        let recorder = Rc::new(Recorder::start(session));
+        drawer = drawer.with_events({
+            let sender = sender.clone();
+            let recorder = recorder.clone();
+            let resume = settings.hotkeys().resume;
+            let help = settings.hotkeys().drawing_help();
+
+            move |event| {
+                let instruction = match event {
+                    DrawEvent::Paused(reason) => {
+                        Instruction::UpdateStatus(format!("{} - Resume with {}", reason, resume))
+                    }
+                    DrawEvent::Resumed => Instruction::UpdateStatus(format!("Drawing - {}", help)),
+                    DrawEvent::Progress(progress) => {
+                        recorder.record(progress.steps);
+                        Instruction::ShowProgress(progress.fraction(), progress.to_string())
+                    }
+                    // the summary counts what was drawn
+                    DrawEvent::Planned(..) => return,
+                    DrawEvent::Verified(Ok(0)) => return,
+                    DrawEvent::Verified(Ok(wrong)) => Instruction::UpdateStatus(format!(
+                        "Redrawing {} missing pixels - {}",
+                        wrong, help
+                    )),
+                    DrawEvent::Verified(Err(err)) => {
+                        Instruction::UpdateStatus(format!("Can't verify the drawing: {}", err))
+                    }
+                };
+                Message::send(sender.clone(), instruction);
+            }
+        });
+
+        Message::send(sender.clone(), Instruction::UpdateStatus(status));
+        Message::send(
+            sender.clone(),
+            Instruction::ShowProgress(0.0, String::new()),
+        );
+
+        // focus the drawing window before drawing
+        desktop.move_cursor(color_x, color_y);
+        desktop.left_click(ClickType::Once);
+
+        drawer_paused.store(false, Ordering::Relaxed);
+        drawer_running.store(true, Ordering::Relaxed);
+        let summary = drawer.draw(desktop, &image, drawer_running.clone());
+        drawer_running.store(false, Ordering::Relaxed);
+        recorder.finish(&summary);
+
+        let status = match drawer.stop_reason() {
+            Some(reason) => format!("Cancelled - {} - {}", reason, summary),
+            None => summary.to_string(),
+        };
+        Message::send(sender, Instruction::UpdateStatus(status));
+    }
+
+    // the image selected in the search results
+    fn selected_image(
+        gtk: &GTK,
+        images_list: &Arc<RwLock<Vec<DynamicImage>>>,
+    ) -> Option<DynamicImage> {
+        gtk.images_view
+            .get_selected_items()
+            .first()
+            .map(|tree_path| {
+                images_list.read().unwrap()[tree_path.get_indices()[0] as usize].clone()
+            })
+    }
+
+    fn image_from_clipboard(sender: Sender<Message>) -> Option<DynamicImage> {
+        match image_converter::image_from_clipboard() {
+            Ok(image) => Some(image),
+            Err(err) => {
+                Message::send(sender, Instruction::UpdateStatus(err));
+                None
+            }
+        }
     }
 
     fn set_status(label: Label, status: &str) {
@@ -391,6 +803,7 @@ impl GUI {
         settings: Arc<RwLock<Settings>>,
         images_list: Arc<RwLock<Vec<DynamicImage>>>,
         uuid_list: Arc<RwLock<Vec<Uuid>>>,
+        hotkeys: Option<hotkeys::Listener>,
         gtk: GTK,
     ) {
         let mut current_image_uuid = Uuid::nil();
@@ -399,8 +812,41 @@ impl GUI {
             let label = gtk.status.clone();
 
             match msg.instruction {
-                Instruction::UpdateSettings => settings.write().unwrap().load_from_gtk(gtk.clone()),
+                Instruction::UpdateSettings => {
+                    let mut settings = settings.write().unwrap();
+                    settings.load_from_gtk(gtk.clone());
+
+                    if let Some(hotkeys) = &hotkeys {
+                        hotkeys.set_hotkeys(settings.hotkeys());
+                    }
+                }
+                Instruction::ShowSettings => settings.write().unwrap().save_to_gtk(gtk.clone()),
                 Instruction::UpdateStatus(status) => GUI::set_status(label, &status),
+                Instruction::ShowProgress(fraction, text) => {
+                    gtk.progress.set_fraction(fraction);
+                    gtk.progress.set_text(Some(&text));
+                }
+                Instruction::ShowPreview(preview, info) => {
+                    let (width, height) = preview.dimensions();
+                    let pixbuf = Pixbuf::new_from_mut_slice(
+                        preview.into_raw(),
+                        Colorspace::Rgb,
+                        false,
+                        8,
+                        width as _,
+                        height as _,
+                        (width * 3) as _,
+                    );
+
+                    gtk.preview.set_from_pixbuf(Some(&pixbuf));
+                    gtk.preview_info.set_text(&info);
+                }
+                Instruction::ClearPreview => {
+                    gtk.preview
+                        .set_from_icon_name(Some("image-missing"), gtk::IconSize::Dialog);
+                    gtk.preview_info
+                        .set_text("Select an image to see a preview");
+                }
                 Instruction::NewImages(uuid) => {
                     images_list.write().unwrap().clear();
                     gtk.images_store.clear();
@@ -419,6 +865,25 @@ impl GUI {
                         }
                     }
                 }
+                // the drawer takes the clipboard if no search result is selected
+                Instruction::DrawClipboard => {
+                    gtk.images_view.unselect_all();
+                    gtk.draw.clicked();
+                }
+                Instruction::NextResult => {
+                    let count = images_list.read().unwrap().len() as i32;
+                    let next = gtk
+                        .images_view
+                        .get_selected_items()
+                        .first()
+                        .map_or(0, |tree_path| tree_path.get_indices()[0] + 1);
+
+                    if count > 0 {
+                        let tree_path = gtk::TreePath::new_from_indicesv(&[next % count]);
+                        gtk.images_view.select_path(&tree_path);
+                        gtk.images_view.scroll_to_path(&tree_path, false, 0.0, 0.0);
+                    }
+                }
             };
 
             if let Some(uuid) = msg.uuid {
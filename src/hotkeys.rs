@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::TryFrom,
+    ffi::CString,
+    fmt, mem,
+    os::raw::{c_int, c_uint},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use x11::xlib;
+
+// how often the hotkey thread looks for key presses and changed bindings
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// names in hotkeys and their X modifier masks, in the order they are written
+const MODIFIERS: [(&str, c_uint); 4] = [
+    ("Ctrl", xlib::ControlMask),
+    ("Shift", xlib::ShiftMask),
+    ("Alt", xlib::Mod1Mask),
+    ("Super", xlib::Mod4Mask),
+];
+
+// Caps Lock and Num Lock, hotkeys work no matter if they are on
+const IGNORED_MODIFIERS: [c_uint; 2] = [xlib::LockMask, xlib::Mod2Mask];
+
+/// What a hotkey does
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Action {
+    DrawClipboard,
+    Pause,
+    Resume,
+    Abort,
+    NextResult,
+}
+
+impl Action {
+    /// Pausing and cancelling only work while drawing, so the keys stay usable otherwise
+    fn only_while_drawing(self) -> bool {
+        matches!(self, Action::Pause | Action::Resume | Action::Abort)
+    }
+}
+
+/// A key with modifiers, written like `Ctrl+Alt+d` with the key as an X keysym name
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct Hotkey {
+    modifiers: c_uint,
+    key: String,
+}
+
+impl Hotkey {
+    pub fn parse(text: &str) -> Result<Hotkey, String> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| format!("Hotkey without a key: {}", text))?;
+
+        let mut modifiers = 0;
+        for part in parts {
+            let mask = match part.to_lowercase().as_str() {
+                "ctrl" | "control" => xlib::ControlMask,
+                "shift" => xlib::ShiftMask,
+                "alt" => xlib::Mod1Mask,
+                "super" | "win" => xlib::Mod4Mask,
+                _ => return Err(format!("Unknown modifier {} in hotkey {}", part, text)),
+            };
+            modifiers |= mask;
+        }
+
+        Ok(Hotkey {
+            modifiers,
+            key: key.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Hotkey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (name, mask) in MODIFIERS.iter() {
+            if self.modifiers & mask != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+
+        write!(f, "{}", self.key)
+    }
+}
+
+impl TryFrom<String> for Hotkey {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Hotkey, String> {
+        Hotkey::parse(&text)
+    }
+}
+
+impl From<Hotkey> for String {
+    fn from(hotkey: Hotkey) -> String {
+        hotkey.to_string()
+    }
+}
+
+/// The keys for every action
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Hotkeys {
+    pub draw_clipboard: Hotkey,
+    pub pause: Hotkey,
+    pub resume: Hotkey,
+    pub abort: Hotkey,
+    pub next_result: Hotkey,
+}
+
+impl Hotkeys {
+    fn bindings(&self) -> [(Action, &Hotkey); 5] {
+        [
+            (Action::DrawClipboard, &self.draw_clipboard),
+            (Action::Pause, &self.pause),
+            (Action::Resume, &self.resume),
+            (Action::Abort, &self.abort),
+            (Action::NextResult, &self.next_result),
+        ]
+    }
+
+    /// Status line while drawing
+    pub fn drawing_help(&self) -> String {
+        format!(
+            "Pause with {}, resume with {}, cancel with {}",
+            self.pause, self.resume, self.abort
+        )
+    }
+}
+
+impl Default for Hotkeys {
+    fn default() -> Hotkeys {
+        let hotkey = |text| Hotkey::parse(text).unwrap();
+
+        Hotkeys {
+            draw_clipboard: hotkey("Ctrl+Alt+d"),
+            pause: hotkey("F9"),
+            resume: hotkey("F10"),
+            abort: hotkey("Escape"),
+            next_result: hotkey("Ctrl+Alt+n"),
+        }
+    }
+}
+
+/// Pauses, resumes or cancels the drawing, other actions are ignored
+pub fn control_drawing(action: Action, running: &AtomicBool, paused: &AtomicBool) {
+    match action {
+        Action::Pause => paused.store(true, Ordering::Relaxed),
+        Action::Resume => paused.store(false, Ordering::Relaxed),
+        Action::Abort => running.store(false, Ordering::Relaxed),
+        Action::DrawClipboard | Action::NextResult => (),
+    }
+}
+
+/// Changes the keys of a running listener
+#[derive(Clone)]
+pub struct Listener {
+    hotkeys: Arc<Mutex<Hotkeys>>,
+}
+
+impl Listener {
+    pub fn set_hotkeys(&self, hotkeys: Hotkeys) {
+        *self.hotkeys.lock().unwrap() = hotkeys;
+    }
+}
+
+// set by the error handler, grabbing fails if another program already uses the key
+static GRAB_FAILED: AtomicBool = AtomicBool::new(false);
+// the handler that was installed before grabbing, it gets every other error
+static PREVIOUS_HANDLER: Mutex<Option<ErrorHandler>> = Mutex::new(None);
+
+type ErrorHandler = unsafe extern "C" fn(*mut xlib::Display, *mut xlib::XErrorEvent) -> c_int;
+
+unsafe extern "C" fn record_error(
+    display: *mut xlib::Display,
+    error: *mut xlib::XErrorEvent,
+) -> c_int {
+    if (*error).error_code == xlib::BadAccess {
+        GRAB_FAILED.store(true, Ordering::Relaxed);
+        return 0;
+    }
+
+    match *PREVIOUS_HANDLER.lock().unwrap() {
+        Some(previous) => previous(display, error),
+        None => 0,
+    }
+}
+
+struct Grab {
+    action: Action,
+    keycode: c_int,
+    modifiers: c_uint,
+}
+
+/// Grabs the hotkeys on the whole screen and calls `on_action` when one is pressed.
+/// The keys for pausing, resuming and cancelling are only grabbed while `running` is set.
+pub fn start<F>(
+    hotkeys: Hotkeys,
+    running: Arc<AtomicBool>,
+    on_action: F,
+) -> Result<Listener, String>
+where
+    F: Fn(Action) + Send + 'static,
+{
+    let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+    if display.is_null() {
+        return Err("Could not open Display for the hotkeys".into());
+    }
+    // the display is only used by the hotkey thread from now on
+    let display = display as usize;
+
+    let listener = Listener {
+        hotkeys: Arc::new(Mutex::new(hotkeys)),
+    };
+    let hotkeys = listener.hotkeys.clone();
+
+    thread::spawn(move || unsafe {
+        let display = display as *mut xlib::Display;
+        let root = xlib::XDefaultRootWindow(display);
+
+        let mut grabbed: Option<(Hotkeys, bool)> = None;
+        let mut grabs = Vec::new();
+
+        loop {
+            let wanted = (
+                hotkeys.lock().unwrap().clone(),
+                running.load(Ordering::Relaxed),
+            );
+
+            if grabbed.as_ref() != Some(&wanted) {
+                ungrab(display, root, &grabs);
+                grabs = grab(display, root, &wanted.0, wanted.1);
+                grabbed = Some(wanted);
+            }
+
+            while xlib::XPending(display) > 0 {
+                let mut event: xlib::XEvent = mem::zeroed();
+                xlib::XNextEvent(display, &mut event);
+
+                if event.get_type() != xlib::KeyPress {
+                    continue;
+                }
+
+                let key = event.key;
+                let modifiers =
+                    key.state & !IGNORED_MODIFIERS.iter().fold(0, |all, mask| all | mask);
+                let pressed = grabs.iter().find(|grab| {
+                    grab.keycode == key.keycode as c_int && grab.modifiers == modifiers
+                });
+
+                if let Some(grab) = pressed {
+                    on_action(grab.action);
+                }
+            }
+
+            thread::sleep(POLL_INTERVAL);
+        }
+    });
+
+    Ok(listener)
+}
+
+// every combination of the ignored modifiers
+fn ignored_combinations() -> impl Iterator<Item = c_uint> {
+    (0..1 << IGNORED_MODIFIERS.len()).map(|bits: u32| {
+        IGNORED_MODIFIERS
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| bits & (1 << index) != 0)
+            .fold(0, |all, (_, mask)| all | mask)
+    })
+}
+
+unsafe fn grab(
+    display: *mut xlib::Display,
+    root: xlib::Window,
+    hotkeys: &Hotkeys,
+    running: bool,
+) -> Vec<Grab> {
+    let mut grabs = Vec::new();
+
+    for (action, hotkey) in hotkeys.bindings().iter() {
+        if action.only_while_drawing() && !running {
+            continue;
+        }
+
+        let name = CString::new(hotkey.key.as_str()).unwrap_or_default();
+        let keysym = xlib::XStringToKeysym(name.as_ptr());
+        let keycode = xlib::XKeysymToKeycode(display, keysym);
+        if keysym == 0 || keycode == 0 {
+            println!("Unknown key in hotkey {}", hotkey);
+            continue;
+        }
+
+        // only the errors of the grab are caught, the handler is shared by the whole process
+        xlib::XSync(display, xlib::False);
+        GRAB_FAILED.store(false, Ordering::Relaxed);
+        *PREVIOUS_HANDLER.lock().unwrap() = xlib::XSetErrorHandler(Some(record_error));
+
+        for ignored in ignored_combinations() {
+            xlib::XGrabKey(
+                display,
+                keycode.into(),
+                hotkey.modifiers | ignored,
+                root,
+                xlib::False,
+                xlib::GrabModeAsync,
+                xlib::GrabModeAsync,
+            );
+        }
+        xlib::XSync(display, xlib::False);
+        xlib::XSetErrorHandler(*PREVIOUS_HANDLER.lock().unwrap());
+
+        if GRAB_FAILED.load(Ordering::Relaxed) {
+            println!("Hotkey {} is already used by another program", hotkey);
+        }
+
+        grabs.push(Grab {
+            action: *action,
+            keycode: keycode.into(),
+            modifiers: hotkey.modifiers,
+        });
+    }
+
+    grabs
+}
+
+unsafe fn ungrab(display: *mut xlib::Display, root: xlib::Window, grabs: &[Grab]) {
+    for grab in grabs {
+        for ignored in ignored_combinations() {
+            xlib::XUngrabKey(display, grab.keycode, grab.modifiers | ignored, root);
+        }
+    }
+    xlib::XSync(display, xlib::False);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hotkeys() {
+        let hotkey = Hotkey::parse("ctrl + alt+d").unwrap();
+
+        assert_eq!(hotkey.modifiers, xlib::ControlMask | xlib::Mod1Mask);
+        assert_eq!(hotkey.key, "d");
+        assert_eq!(hotkey.to_string(), "Ctrl+Alt+d");
+        assert_eq!(
+            Hotkey::parse("Alt+Shift+F9").unwrap().to_string(),
+            "Shift+Alt+F9"
+        );
+        assert_eq!(Hotkey::parse("Escape").unwrap().modifiers, 0);
+
+        assert!(Hotkey::parse("").is_err());
+        assert!(Hotkey::parse("Ctrl+").is_err());
+        assert!(Hotkey::parse("Hyper+d").is_err());
+    }
+
+    #[test]
+    fn stores_hotkeys_as_text() {
+        let json = serde_json::to_string(&Hotkeys::default()).unwrap();
+
+        assert!(json.contains(r#""draw_clipboard":"Ctrl+Alt+d""#));
+        assert_eq!(
+            serde_json::from_str::<Hotkeys>(&json).unwrap(),
+            Hotkeys::default()
+        );
+    }
+
+    #[test]
+    fn ignores_caps_and_num_lock() {
+        let combinations: Vec<c_uint> = ignored_combinations().collect();
+
+        assert_eq!(
+            combinations,
+            vec![
+                0,
+                xlib::LockMask,
+                xlib::Mod2Mask,
+                xlib::LockMask | xlib::Mod2Mask
+            ]
+        );
+    }
+}
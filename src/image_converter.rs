@@ -1,25 +1,253 @@
-use crate::colors::*;
+use crate::{
+    color_distance::ColorDistance, colors::*, edges, palette::Palette, quantize, settings::Settings,
+};
 use image::{
     imageops::{self, colorops::ColorMap},
     DynamicImage, ImageBuffer, ImageFormat, Pixel, Rgb,
 };
-use std::process::Command;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    process::Command,
+    sync::{Arc, Mutex, OnceLock},
+};
 
 pub type Image = ImageBuffer<image::Rgb<u8>, Vec<u8>>;
 
+// (x offset, y offset, weight) of the error that is passed on to later pixels,
+// atkinson only passes on 6/8 of the error which keeps more contrast
+const ATKINSON: [(i32, u32, f32); 6] = [
+    (1, 0, 1.0),
+    (2, 0, 1.0),
+    (-1, 1, 1.0),
+    (0, 1, 1.0),
+    (1, 1, 1.0),
+    (0, 2, 1.0),
+];
+const SIERRA: [(i32, u32, f32); 10] = [
+    (1, 0, 5.0),
+    (2, 0, 3.0),
+    (-2, 1, 2.0),
+    (-1, 1, 4.0),
+    (0, 1, 5.0),
+    (1, 1, 4.0),
+    (2, 1, 2.0),
+    (-1, 2, 2.0),
+    (0, 2, 3.0),
+    (1, 2, 2.0),
+];
+const JARVIS_JUDICE_NINKE: [(i32, u32, f32); 12] = [
+    (1, 0, 7.0),
+    (2, 0, 5.0),
+    (-2, 1, 3.0),
+    (-1, 1, 5.0),
+    (0, 1, 7.0),
+    (1, 1, 5.0),
+    (2, 1, 3.0),
+    (-2, 2, 1.0),
+    (-1, 2, 3.0),
+    (0, 2, 5.0),
+    (1, 2, 3.0),
+    (2, 2, 1.0),
+];
+
+// how far ordered dithering moves a color, about the distance between two close palette colors
+const ORDERED_SPREAD: f32 = 64.0;
+const BLUE_NOISE_SIZE: u32 = 32;
+// how far a picked pixel pushes away the next ones while building the blue noise
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+// bits of every channel that are used to look up the nearest palette color
+const TABLE_BITS: u32 = 6;
+
+/// How colors that are not in the palette get approximated
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Dithering {
+    /// Every pixel gets the nearest color
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Sierra,
+    JarvisJudiceNinke,
+    Bayer2,
+    Bayer4,
+    Bayer8,
+    BlueNoise,
+}
+
+impl Dithering {
+    pub const ALL: [Dithering; 9] = [
+        Dithering::None,
+        Dithering::FloydSteinberg,
+        Dithering::Atkinson,
+        Dithering::Sierra,
+        Dithering::JarvisJudiceNinke,
+        Dithering::Bayer2,
+        Dithering::Bayer4,
+        Dithering::Bayer8,
+        Dithering::BlueNoise,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Dithering::None => "none",
+            Dithering::FloydSteinberg => "floyd-steinberg",
+            Dithering::Atkinson => "atkinson",
+            Dithering::Sierra => "sierra",
+            Dithering::JarvisJudiceNinke => "jarvis-judice-ninke",
+            Dithering::Bayer2 => "bayer2",
+            Dithering::Bayer4 => "bayer4",
+            Dithering::Bayer8 => "bayer8",
+            Dithering::BlueNoise => "blue-noise",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Dithering> {
+        Dithering::ALL
+            .iter()
+            .copied()
+            .find(|dithering| dithering.id() == id)
+    }
+
+    /// Replaces every pixel with a color of the palette
+    pub fn apply(self, image: &mut Image, color_map: &SkribblColorMap) {
+        match self {
+            Dithering::None => {
+                for pixel in image.pixels_mut() {
+                    color_map.map_color(pixel);
+                }
+            }
+            // the image crate rounds a bit differently than diffuse_error,
+            // keep using it to not change existing drawings
+            Dithering::FloydSteinberg => imageops::dither(image, color_map),
+            Dithering::Atkinson => diffuse_error(image, color_map, &ATKINSON, 8.0),
+            Dithering::Sierra => diffuse_error(image, color_map, &SIERRA, 32.0),
+            Dithering::JarvisJudiceNinke => {
+                diffuse_error(image, color_map, &JARVIS_JUDICE_NINKE, 48.0)
+            }
+            Dithering::Bayer2 => ordered(image, color_map, &bayer_matrix(2), 2),
+            Dithering::Bayer4 => ordered(image, color_map, &bayer_matrix(4), 4),
+            Dithering::Bayer8 => ordered(image, color_map, &bayer_matrix(8), 8),
+            Dithering::BlueNoise => ordered(
+                image,
+                color_map,
+                &blue_noise(BLUE_NOISE_SIZE),
+                BLUE_NOISE_SIZE,
+            ),
+        }
+    }
+}
+
+/// Draws only the outlines of the image
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Sketch {
+    Off,
+    /// Black lines
+    Black,
+    /// Lines in the color of the region they are in
+    Colored,
+}
+
+impl Sketch {
+    pub const ALL: [Sketch; 3] = [Sketch::Off, Sketch::Black, Sketch::Colored];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Sketch::Off => "off",
+            Sketch::Black => "black",
+            Sketch::Colored => "colored",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Sketch> {
+        Sketch::ALL.iter().copied().find(|sketch| sketch.id() == id)
+    }
+}
+
+/// Fits the image into `width` x `height` pixels of the drawing and maps it to the palette
 pub fn convert(
     image: DynamicImage,
-    dither: bool,
+    dithering: Dithering,
+    color_map: &SkribblColorMap,
     grayscale: bool,
     scale: f64,
     width: u32,
     height: u32,
 ) -> Image {
+    let mut rgb = thumbnail(image, grayscale, scale, width, height);
+
+    dithering.apply(&mut rgb, color_map);
+
+    rgb
+}
+
+/// Fits the image into `width` x `height` pixels of the drawing and turns its edges
+/// into lines that are one pixel wide
+pub fn sketch(
+    image: DynamicImage,
+    sketch: Sketch,
+    color_map: &SkribblColorMap,
+    grayscale: bool,
+    scale: f64,
+    width: u32,
+    height: u32,
+) -> Image {
+    let thumbnail = thumbnail(image, grayscale, scale, width, height);
+    let lines = edges::lines(&thumbnail);
+
+    let mut regions = thumbnail;
+    Dithering::None.apply(&mut regions, color_map);
+
+    // lines get the palette color closest to black, not every palette has black
+    let mut ink = Rgb([BLACK.r, BLACK.g, BLACK.b]);
+    color_map.map_color(&mut ink);
+
+    ImageBuffer::from_fn(regions.width(), regions.height(), |x, y| {
+        if !lines[(y * regions.width() + x) as usize] {
+            Rgb([WHITE.r, WHITE.g, WHITE.b])
+        } else if sketch == Sketch::Colored {
+            dominant_color(&regions, x, y, ink)
+        } else {
+            ink
+        }
+    })
+}
+
+// most common color around a pixel besides white, lines on white get the ink
+fn dominant_color(image: &Image, x: u32, y: u32, ink: Rgb<u8>) -> Rgb<u8> {
+    let mut counts: Vec<(Rgb<u8>, u32)> = Vec::new();
+
+    for around_y in y.saturating_sub(2)..(y + 3).min(image.height()) {
+        for around_x in x.saturating_sub(2)..(x + 3).min(image.width()) {
+            let pixel = *image.get_pixel(around_x, around_y);
+            if pixel == Rgb([WHITE.r, WHITE.g, WHITE.b]) {
+                continue;
+            }
+
+            match counts.iter_mut().find(|(color, _)| *color == pixel) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((pixel, 1)),
+            }
+        }
+    }
+
+    // the first one wins a tie
+    counts
+        .iter()
+        .fold(None, |best: Option<&(Rgb<u8>, u32)>, entry| match best {
+            Some(best) if best.1 >= entry.1 => Some(best),
+            _ => Some(entry),
+        })
+        .map_or(ink, |&(color, _)| color)
+}
+
+// resizes the image to fit into the drawing, transparent pixels become white
+fn thumbnail(image: DynamicImage, grayscale: bool, scale: f64, width: u32, height: u32) -> Image {
     let rgba = image.to_rgba();
 
-    // canvas is x814y611, but a pixel is 3x3
     let (thumbnail_x, thumbnail_y) =
-        resize_dimensions(rgba.width(), rgba.height(), width / 3, height / 3, false);
+        resize_dimensions(rgba.width(), rgba.height(), width, height, false);
     let thumbnail = imageops::thumbnail(
         &rgba,
         (f64::from(thumbnail_x) * scale) as u32,
@@ -49,18 +277,192 @@ pub fn convert(
         }
     }
 
-    let color_map = SkribblColorMap;
-    if dither {
-        imageops::dither(&mut rgb, &color_map);
-    } else {
-        for pixel in rgb.pixels_mut() {
-            let mut new = pixel.to_rgb();
-            color_map.map_color(&mut new);
-            *pixel = new;
+    rgb
+}
+
+// maps pixels from left to right and spreads the difference to the original over the next ones
+fn diffuse_error(
+    image: &mut Image,
+    color_map: &SkribblColorMap,
+    kernel: &[(i32, u32, f32)],
+    divisor: f32,
+) {
+    let (width, height) = image.dimensions();
+    let mut errors = vec![[0.0f32; 3]; (width * height) as usize];
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel_mut(x, y);
+            let error = errors[(y * width + x) as usize];
+
+            let mut wanted = [0.0; 3];
+            for (channel, wanted) in wanted.iter_mut().enumerate() {
+                *wanted = (f32::from(pixel[channel]) + error[channel])
+                    .round()
+                    .clamp(0.0, 255.0);
+                pixel[channel] = *wanted as u8;
+            }
+
+            color_map.map_color(pixel);
+            let pixel = *pixel;
+
+            for &(offset_x, offset_y, weight) in kernel {
+                let (next_x, next_y) = (x as i32 + offset_x, y + offset_y);
+                if next_x < 0 || next_x >= width as i32 || next_y >= height {
+                    continue;
+                }
+
+                let next = &mut errors[(next_y * width + next_x as u32) as usize];
+                for channel in 0..3 {
+                    next[channel] +=
+                        (wanted[channel] - f32::from(pixel[channel])) * weight / divisor;
+                }
+            }
         }
     }
+}
 
-    rgb
+// offsets every pixel by a repeating threshold map before picking the nearest color,
+// thresholds are ranks from 0 to size * size - 1
+fn ordered(image: &mut Image, color_map: &SkribblColorMap, thresholds: &[u32], size: u32) {
+    let levels = (size * size) as f32;
+
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let rank = thresholds[((y % size) * size + x % size) as usize];
+        let offset = ((rank as f32 + 0.5) / levels - 0.5) * ORDERED_SPREAD;
+
+        for channel in pixel.0.iter_mut() {
+            *channel = (f32::from(*channel) + offset).round().clamp(0.0, 255.0) as u8;
+        }
+
+        color_map.map_color(pixel);
+    }
+}
+
+// every matrix is built from four copies of the one half its size
+fn bayer_matrix(size: u32) -> Vec<u32> {
+    if size == 1 {
+        return vec![0];
+    }
+
+    let half = size / 2;
+    let smaller = bayer_matrix(half);
+
+    let mut matrix = vec![0; (size * size) as usize];
+    for y in 0..size {
+        for x in 0..size {
+            let quadrant = [0, 2, 3, 1][((y / half) * 2 + x / half) as usize];
+            matrix[(y * size + x) as usize] =
+                4 * smaller[((y % half) * half + x % half) as usize] + quadrant;
+        }
+    }
+
+    matrix
+}
+
+// ranks the pixels by always picking the one that's furthest away from all picked ones,
+// so every threshold level is spread evenly without a visible pattern
+fn blue_noise(size: u32) -> Vec<u32> {
+    let count = (size * size) as usize;
+
+    // energy a picked pixel adds to the pixels around it, wrapping around the edges
+    let splat: Vec<f32> = (0..size * size)
+        .map(|index| {
+            let (x, y) = (index % size, index / size);
+            let (x, y) = (x.min(size - x) as f32, y.min(size - y) as f32);
+
+            (-(x * x + y * y) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+        })
+        .collect();
+
+    let mut energy = vec![0.0f32; count];
+    let mut ranks = vec![None; count];
+
+    for rank in 0..count as u32 {
+        let void = (0..count)
+            .filter(|&index| ranks[index].is_none())
+            .min_by(|&a, &b| energy[a].partial_cmp(&energy[b]).unwrap())
+            .unwrap();
+        ranks[void] = Some(rank);
+
+        let (void_x, void_y) = (void as u32 % size, void as u32 / size);
+        for (index, energy) in energy.iter_mut().enumerate() {
+            let x = (index as u32 % size + size - void_x) % size;
+            let y = (index as u32 / size + size - void_y) % size;
+
+            *energy += splat[(y * size + x) as usize];
+        }
+    }
+
+    ranks.into_iter().map(Option::unwrap).collect()
+}
+
+pub fn convert_with_settings(image: DynamicImage, settings: &Settings) -> Image {
+    let distance = settings
+        .color_distance
+        .unwrap_or_else(|| Settings::default().color_distance.unwrap());
+    let grayscale = settings.grayscale.unwrap_or(false);
+    let (width, height) = drawing_size(settings);
+
+    let color_map = match colors_for_image(&image, settings) {
+        Some(palette) => SkribblColorMap::for_image(&palette, distance),
+        None => SkribblColorMap::new(&settings.palette().unwrap_or_default(), distance),
+    };
+
+    match settings.sketch() {
+        Sketch::Off => convert(
+            image,
+            settings.dithering(),
+            &color_map,
+            grayscale,
+            settings.scale,
+            width,
+            height,
+        ),
+        mode => sketch(
+            image,
+            mode,
+            &color_map,
+            grayscale,
+            settings.scale,
+            width,
+            height,
+        ),
+    }
+}
+
+// a pixel of the drawing covers pixel_size x pixel_size pixels of the canvas
+fn drawing_size(settings: &Settings) -> (u32, u32) {
+    (
+        (f64::from(settings.drawing_width) / settings.pixel_size()) as u32,
+        (f64::from(settings.drawing_height) / settings.pixel_size()) as u32,
+    )
+}
+
+/// The colors that are picked for the image, None if it may use the whole palette
+pub fn colors_for_image(image: &DynamicImage, settings: &Settings) -> Option<Palette> {
+    if settings.custom_colors().is_none() && settings.max_colors().is_none() {
+        return None;
+    }
+
+    let (width, height) = drawing_size(settings);
+    let grayscale = settings.grayscale.unwrap_or(false);
+    let pixels = thumbnail(image.clone(), grayscale, settings.scale, width, height);
+
+    if let Some(count) = settings.custom_colors() {
+        return Some(quantize::adaptive_palette(&pixels, count));
+    }
+
+    let palette = settings.palette().ok()?;
+    let distance = settings
+        .color_distance
+        .unwrap_or_else(|| Settings::default().color_distance.unwrap());
+    let colors = quantize::best_colors(&pixels, &palette, settings.max_colors()?, distance);
+
+    Some(Palette {
+        name: format!("{} of the {} colors", colors.len(), palette.name),
+        rows: vec![colors],
+    })
 }
 
 pub fn image_from_clipboard() -> Result<DynamicImage, String> {
@@ -118,62 +520,397 @@ pub fn resize_dimensions(
     }
 }
 
-pub struct SkribblColorMap;
+// lookup tables of every palette and distance that was used
+type Tables = HashMap<(Vec<Color>, ColorDistance), Arc<Vec<u8>>>;
+// tables of the palettes picked for the last images, the newest last
+type RecentTables = VecDeque<((Vec<Color>, ColorDistance), Arc<Vec<u8>>)>;
+// palettes picked for an image are kept for its preview and drawing, not forever
+const RECENT_TABLES: usize = 8;
+
+/// Maps colors to the nearest palette color with a precomputed lookup table
+pub struct SkribblColorMap {
+    colors: Vec<Color>,
+    table: Arc<Vec<u8>>,
+}
+
+impl SkribblColorMap {
+    pub fn new(palette: &Palette, distance: ColorDistance) -> SkribblColorMap {
+        // every table is only built once, building the CIEDE2000 one takes a while
+        static TABLES: OnceLock<Mutex<Tables>> = OnceLock::new();
+
+        let colors: Vec<Color> = palette.colors().cloned().collect();
+        let table = TABLES
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap()
+            .entry((colors.clone(), distance))
+            .or_insert_with(|| Arc::new(build_table(&colors, distance)))
+            .clone();
+
+        SkribblColorMap { colors, table }
+    }
+
+    /// For palettes picked for one image, only the tables of the last few are kept.
+    /// The time budget and the preview convert the same image again and again.
+    pub fn for_image(palette: &Palette, distance: ColorDistance) -> SkribblColorMap {
+        static RECENT: OnceLock<Mutex<RecentTables>> = OnceLock::new();
+
+        let colors: Vec<Color> = palette.colors().cloned().collect();
+        let key = (colors.clone(), distance);
+        let mut recent = RECENT.get_or_init(Default::default).lock().unwrap();
+
+        let table = match recent.iter().position(|(used, _)| *used == key) {
+            Some(index) => recent.remove(index).unwrap().1,
+            None => Arc::new(build_table(&colors, distance)),
+        };
+        recent.push_back((key, table.clone()));
+        if recent.len() > RECENT_TABLES {
+            recent.pop_front();
+        }
+
+        SkribblColorMap { colors, table }
+    }
+}
 
 impl ColorMap for SkribblColorMap {
     type Color = Rgb<u8>;
 
-    // we are not implementing this, because it's unused by the dithering function
-    fn index_of(&self, _: &Rgb<u8>) -> usize {
-        0
+    // index of the color in the palette, row by row
+    fn index_of(&self, color: &Rgb<u8>) -> usize {
+        let cell = |channel: u8| usize::from(channel >> (8 - TABLE_BITS));
+        let index =
+            (cell(color[0]) << (2 * TABLE_BITS)) | (cell(color[1]) << TABLE_BITS) | cell(color[2]);
+
+        usize::from(self.table[index])
     }
 
     fn map_color(&self, color: &mut Rgb<u8>) {
-        let mut diffs = vec![
-            difference(color.0, WHITE),
-            difference(color.0, LIGHT_GREY),
-            difference(color.0, LIGHT_RED),
-            difference(color.0, LIGHT_ORANGE),
-            difference(color.0, LIGHT_YELLOW),
-            difference(color.0, LIGHT_GREEN),
-            difference(color.0, LIGHT_CYAN),
-            difference(color.0, LIGHT_BLUE),
-            difference(color.0, LIGHT_MAGENTA),
-            difference(color.0, LIGHT_PINK),
-            difference(color.0, LIGHT_BROWN),
-            difference(color.0, BLACK),
-            difference(color.0, DARK_GREY),
-            difference(color.0, DARK_RED),
-            difference(color.0, DARK_ORANGE),
-            difference(color.0, DARK_YELLOW),
-            difference(color.0, DARK_GREEN),
-            difference(color.0, DARK_CYAN),
-            difference(color.0, DARK_BLUE),
-            difference(color.0, DARK_MAGENTA),
-            difference(color.0, DARK_PINK),
-            difference(color.0, DARK_BROWN),
-        ];
-
-        diffs.sort_by(|a, b| a.0.cmp(&b.0));
-
-        let best_color = &diffs[0].1;
+        let index = self.index_of(color);
+        let best_color = &self.colors[index];
+
         color.0[0] = best_color.r;
         color.0[1] = best_color.g;
         color.0[2] = best_color.b;
     }
 }
 
-fn difference(image_color: [u8; 3], skribbl_color: Color) -> (i32, Color) {
-    let image_r = i32::from(image_color[0]);
-    let image_g = i32::from(image_color[1]);
-    let image_b = i32::from(image_color[2]);
+// nearest palette color for the center of every cell of the table
+fn build_table(colors: &[Color], distance: ColorDistance) -> Vec<u8> {
+    let palette: Vec<[f64; 3]> = colors
+        .iter()
+        .map(|color| distance.coordinates([color.r, color.g, color.b]))
+        .collect();
+
+    let cells = 1 << TABLE_BITS;
+    let center = |cell: u32| ((cell << (8 - TABLE_BITS)) | (1 << (7 - TABLE_BITS))) as u8;
+
+    (0..cells * cells * cells)
+        .map(|index| {
+            let coordinates = distance.coordinates([
+                center(index >> (2 * TABLE_BITS)),
+                center((index >> TABLE_BITS) % cells),
+                center(index % cells),
+            ]);
+
+            palette
+                .iter()
+                .map(|palette_color| distance.compare(coordinates, *palette_color))
+                .enumerate()
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .unwrap()
+                .0 as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{profile_file, PICKER_PROFILE};
 
-    let skribbl_r = i32::from(skribbl_color.r);
-    let skribbl_g = i32::from(skribbl_color.g);
-    let skribbl_b = i32::from(skribbl_color.b);
+    fn palette_colors() -> Vec<Rgb<u8>> {
+        Palette::skribbl()
+            .colors()
+            .map(|color| Rgb([color.r, color.g, color.b]))
+            .collect()
+    }
+
+    fn brightness(image: &Image) -> f64 {
+        let sum: f64 = image
+            .pixels()
+            .map(|pixel| {
+                pixel
+                    .0
+                    .iter()
+                    .map(|&channel| f64::from(channel))
+                    .sum::<f64>()
+            })
+            .sum();
 
-    let diff =
-        (image_r - skribbl_r).abs() + (image_g - skribbl_g).abs() + (image_b - skribbl_b).abs();
+        sum / f64::from(image.width() * image.height() * 3)
+    }
 
-    (diff as i32, skribbl_color)
+    #[test]
+    fn builds_bayer_matrices() {
+        assert_eq!(bayer_matrix(2), vec![0, 2, 3, 1]);
+        assert_eq!(
+            bayer_matrix(4),
+            vec![0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+
+        let mut ranks = bayer_matrix(8);
+        ranks.sort();
+        assert_eq!(ranks, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn blue_noise_spreads_thresholds() {
+        let thresholds = blue_noise(16);
+
+        let mut ranks = thresholds.clone();
+        ranks.sort();
+        assert_eq!(ranks, (0..256).collect::<Vec<_>>());
+
+        // the darkest quarter of the pixels is spread over every 4x4 block
+        for block_y in 0..4 {
+            for block_x in 0..4 {
+                let darkest = (0..16)
+                    .map(|index| (block_y * 4 + index / 4) * 16 + block_x * 4 + index % 4)
+                    .filter(|&index| thresholds[index] < 64)
+                    .count();
+                assert!(darkest >= 2, "block {} {}", block_x, block_y);
+            }
+        }
+    }
+
+    #[test]
+    fn only_uses_palette_colors() {
+        let palette = palette_colors();
+        let gradient = ImageBuffer::from_fn(32, 16, |x, y| {
+            Rgb([(x * 8) as u8, (y * 16) as u8, (255 - x * 8) as u8])
+        });
+
+        for &dithering in Dithering::ALL.iter() {
+            let mut image = gradient.clone();
+            dithering.apply(
+                &mut image,
+                &SkribblColorMap::new(&Palette::skribbl(), ColorDistance::Rgb),
+            );
+
+            assert!(
+                image.pixels().all(|pixel| palette.contains(pixel)),
+                "{:?}",
+                dithering
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_black_and_white() {
+        let original = ImageBuffer::from_fn(16, 16, |x, y| {
+            if (x / 4 + y / 4) % 2 == 0 {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        });
+
+        for &dithering in Dithering::ALL.iter() {
+            let mut image = original.clone();
+            dithering.apply(
+                &mut image,
+                &SkribblColorMap::new(&Palette::skribbl(), ColorDistance::Rgb),
+            );
+
+            assert_eq!(image, original, "{:?}", dithering);
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_brightness_of_grey() {
+        // between dark grey and light grey
+        let grey = ImageBuffer::from_pixel(32, 32, Rgb([108, 108, 108]));
+
+        for &dithering in Dithering::ALL.iter().skip(1) {
+            let mut image = grey.clone();
+            dithering.apply(
+                &mut image,
+                &SkribblColorMap::new(&Palette::skribbl(), ColorDistance::Rgb),
+            );
+
+            let mut colors = image.pixels().collect::<Vec<_>>();
+            colors.sort_by_key(|pixel| pixel.0);
+            colors.dedup();
+
+            assert!(colors.len() >= 2, "{:?}", dithering);
+            assert!(
+                (brightness(&image) - 108.0).abs() < 16.0,
+                "{:?}: {}",
+                dithering,
+                brightness(&image)
+            );
+        }
+
+        let mut image = grey;
+        Dithering::None.apply(
+            &mut image,
+            &SkribblColorMap::new(&Palette::skribbl(), ColorDistance::Rgb),
+        );
+        assert!(image.pixels().all(|pixel| *pixel == image[(0, 0)]));
+    }
+
+    #[test]
+    fn palette_colors_map_to_themselves() {
+        for &distance in ColorDistance::ALL.iter() {
+            let color_map = SkribblColorMap::new(&Palette::skribbl(), distance);
+
+            for (index, color) in palette_colors().into_iter().enumerate() {
+                assert_eq!(color_map.index_of(&color), index, "{:?}", distance);
+            }
+        }
+    }
+
+    #[test]
+    fn maps_to_other_palettes() {
+        let palette = Palette {
+            name: "red and blue".into(),
+            rows: vec![vec![LIGHT_RED], vec![DARK_BLUE]],
+        };
+        let color_map = SkribblColorMap::new(&palette, ColorDistance::Rgb);
+
+        let mut orange = Rgb([255, 113, 0]);
+        let mut black = Rgb([0, 0, 0]);
+        color_map.map_color(&mut orange);
+        color_map.map_color(&mut black);
+
+        assert_eq!(orange, Rgb([LIGHT_RED.r, LIGHT_RED.g, LIGHT_RED.b]));
+        assert_eq!(black, Rgb([DARK_BLUE.r, DARK_BLUE.g, DARK_BLUE.b]));
+    }
+
+    #[test]
+    fn sketches_with_palettes_without_black() {
+        let palette = Palette {
+            name: "no black".into(),
+            rows: vec![vec![WHITE, LIGHT_RED, DARK_BLUE]],
+        };
+        let color_map = SkribblColorMap::new(&palette, ColorDistance::Rgb);
+        let colors: Vec<Rgb<u8>> = palette
+            .colors()
+            .map(|color| Rgb([color.r, color.g, color.b]))
+            .collect();
+        let square = DynamicImage::ImageRgb8(ImageBuffer::from_fn(20, 20, |x, y| {
+            if (5..15).contains(&x) && (5..15).contains(&y) {
+                Rgb([0, 0, 0])
+            } else {
+                Rgb([255, 255, 255])
+            }
+        }));
+
+        for &mode in [Sketch::Black, Sketch::Colored].iter() {
+            let image = sketch(square.clone(), mode, &color_map, false, 1.0, 20, 20);
+            let ink = Rgb([DARK_BLUE.r, DARK_BLUE.g, DARK_BLUE.b]);
+
+            assert!(
+                image.pixels().all(|pixel| colors.contains(pixel)),
+                "{:?}",
+                mode
+            );
+            assert!(image.pixels().any(|&pixel| pixel == ink), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn perceptual_distances_keep_skin_and_sky() {
+        let map = |distance, color| {
+            let mut color = Rgb(color);
+            SkribblColorMap::new(&Palette::skribbl(), distance).map_color(&mut color);
+            color
+        };
+        let rgb = |color: Color| Rgb([color.r, color.g, color.b]);
+
+        let skin = [241, 194, 125];
+        let sky = [135, 206, 235];
+
+        assert_eq!(map(ColorDistance::Rgb, skin), rgb(LIGHT_PINK));
+        assert_eq!(map(ColorDistance::Rgb, sky), rgb(LIGHT_GREY));
+
+        for &distance in ColorDistance::ALL[1..].iter() {
+            assert_eq!(map(distance, skin), rgb(DARK_YELLOW), "{:?}", distance);
+            assert_eq!(map(distance, sky), rgb(LIGHT_CYAN), "{:?}", distance);
+        }
+    }
+
+    #[test]
+    fn reads_old_dither_setting() {
+        let mut settings = Settings {
+            dither: false,
+            dithering: None,
+            ..Settings::default()
+        };
+        assert_eq!(settings.dithering(), Dithering::None);
+
+        settings.dither = true;
+        assert_eq!(settings.dithering(), Dithering::FloydSteinberg);
+
+        settings.dithering = Some(Dithering::Bayer4);
+        assert_eq!(settings.dithering(), Dithering::Bayer4);
+    }
+
+    #[test]
+    fn keeps_the_tables_of_recent_palettes() {
+        let palette = |red: u8| Palette {
+            name: "picked".into(),
+            rows: vec![vec![
+                WHITE,
+                Color::parse(&format!("#{:02x}0000", red)).unwrap(),
+            ]],
+        };
+        let first = SkribblColorMap::for_image(&palette(1), ColorDistance::Rgb);
+
+        let again = SkribblColorMap::for_image(&palette(1), ColorDistance::Rgb);
+        assert!(Arc::ptr_eq(&first.table, &again.table));
+
+        for red in 2..=RECENT_TABLES as u8 + 1 {
+            SkribblColorMap::for_image(&palette(red), ColorDistance::Rgb);
+        }
+        let rebuilt = SkribblColorMap::for_image(&palette(1), ColorDistance::Rgb);
+        assert!(!Arc::ptr_eq(&first.table, &rebuilt.table));
+    }
+
+    #[test]
+    fn picks_custom_colors_for_the_color_picker() {
+        let mut settings = Settings {
+            drawing_width: 36,
+            drawing_height: 36,
+            color_width: 36,
+            color_height: 36,
+            dithering: Some(Dithering::None),
+            profile: Some(profile_file("converter", PICKER_PROFILE)),
+            custom_colors: Some(3),
+            ..Settings::default()
+        };
+        // neither color is in the palette of the game
+        let teal = Rgb([20, 140, 130]);
+        let mauve = Rgb([180, 110, 160]);
+        // shrunk to the 9x9 pixels of the smallest brush without mixing the colors
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(18, 18, |x, _| {
+            if x < 8 {
+                teal
+            } else {
+                mauve
+            }
+        }));
+
+        let converted = convert_with_settings(image.clone(), &settings);
+        assert!(converted
+            .pixels()
+            .all(|pixel| *pixel == teal || *pixel == mauve));
+
+        // games without a color picker keep their palette
+        settings.profile = None;
+        let converted = convert_with_settings(image, &settings);
+        assert!(!converted
+            .pixels()
+            .any(|pixel| *pixel == teal || *pixel == mauve));
+    }
 }
@@ -1,24 +1,52 @@
-mod closing_thread;
+mod brushes;
+mod budget;
+mod calibration;
+mod cli;
+mod color_distance;
 mod colors;
 mod desktop;
 mod drawer;
+mod edges;
+mod fill;
+mod game;
 mod gui;
+mod hotkeys;
 mod image_converter;
 mod image_downloader;
+mod palette;
+mod planner;
+mod quantize;
+mod session;
 mod settings;
+mod simulator;
+mod uinput_desktop;
+mod verify;
 
-use desktop::Desktop;
 use gui::GUI;
 use settings::Settings;
-use std::sync::{atomic::AtomicBool, Arc};
+use std::{
+    process,
+    sync::{atomic::AtomicBool, Arc},
+};
 
 fn main() {
+    let matches = cli::app().get_matches();
+
+    if matches.subcommand_name().is_some() {
+        if let Err(err) = cli::run(&matches) {
+            println!("{}", err);
+            process::exit(1);
+        }
+
+        return;
+    }
+
     let running = Arc::new(AtomicBool::new(false));
-    closing_thread::start(running.clone());
+    let paused = Arc::new(AtomicBool::new(false));
 
     let settings = Settings::load();
-    let desktop = Desktop::new();
+    let desktop = desktop::open(settings.as_ref().ok());
 
-    let gui = GUI::new(settings, desktop, running);
+    let gui = GUI::new(settings, desktop, running, paused);
     gui.run();
 }
@@ -0,0 +1,151 @@
+use crate::{colors::*, drawer::Box};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+// the color maps store palette indices as bytes
+const MAX_COLORS: usize = 256;
+
+/// The colors of a game and how their swatches are laid out
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Debug)]
+pub struct Palette {
+    pub name: String,
+    /// Rows of swatches from the top, every row from the left
+    pub rows: Vec<Vec<Color>>,
+}
+
+impl Palette {
+    /// Two rows of eleven swatches, light colors on top
+    pub fn skribbl() -> Palette {
+        Palette {
+            name: "skribbl.io".into(),
+            rows: vec![
+                vec![
+                    WHITE,
+                    LIGHT_GREY,
+                    LIGHT_RED,
+                    LIGHT_ORANGE,
+                    LIGHT_YELLOW,
+                    LIGHT_GREEN,
+                    LIGHT_CYAN,
+                    LIGHT_BLUE,
+                    LIGHT_MAGENTA,
+                    LIGHT_PINK,
+                    LIGHT_BROWN,
+                ],
+                vec![
+                    BLACK,
+                    DARK_GREY,
+                    DARK_RED,
+                    DARK_ORANGE,
+                    DARK_YELLOW,
+                    DARK_GREEN,
+                    DARK_CYAN,
+                    DARK_BLUE,
+                    DARK_MAGENTA,
+                    DARK_PINK,
+                    DARK_BROWN,
+                ],
+            ],
+        }
+    }
+
+    /// Reads a palette from a JSON file like
+    /// `{"name": "mine", "rows": [["#ffffff", "#000000"]]}`
+    pub fn load(path: &str) -> Result<Palette, String> {
+        let content =
+            fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let palette: Palette = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to read {}: {}", path, err))?;
+
+        palette
+            .check()
+            .map_err(|err| format!("Palette {}: {}", path, err))?;
+        Ok(palette)
+    }
+
+    /// Err if there are no colors or too many
+    pub fn check(&self) -> Result<(), String> {
+        let count = self.colors().count();
+
+        if count == 0 {
+            Err("There are no colors".into())
+        } else if count > MAX_COLORS {
+            Err(format!("More than {} colors", MAX_COLORS))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// All colors, row by row
+    pub fn colors(&self) -> impl Iterator<Item = &Color> {
+        self.rows.iter().flatten()
+    }
+
+    /// Color of the swatch in a column and row
+    pub fn get(&self, column: usize, row: usize) -> Option<&Color> {
+        self.rows.get(row).and_then(|colors| colors.get(column))
+    }
+
+    /// Centers of the swatches, `color_box` is the first one and all have its size
+    pub fn positions(&self, color_box: Box) -> HashMap<Color, ColorCoord> {
+        let xmiddle = color_box.x + (color_box.width / 2);
+        let ymiddle = color_box.y + (color_box.height / 2);
+
+        let mut map = HashMap::new();
+        for (y, colors) in self.rows.iter().enumerate() {
+            for (x, color) in colors.iter().enumerate() {
+                let xpos = xmiddle + color_box.width * x as u32;
+                let ypos = ymiddle + color_box.height * y as u32;
+
+                map.entry(color.clone())
+                    .or_insert(ColorCoord { x: xpos, y: ypos });
+            }
+        }
+
+        map
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::skribbl()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_palettes_from_json() {
+        let json = r##"{"name": "mine", "rows": [["#ffffff", "#000000"], ["#ef130b"]]}"##;
+        let palette: Palette = serde_json::from_str(json).unwrap();
+
+        assert_eq!(palette.rows, vec![vec![WHITE, BLACK], vec![LIGHT_RED]]);
+        assert_eq!(palette.get(0, 1), Some(&LIGHT_RED));
+        assert_eq!(palette.get(1, 1), None);
+        assert!(palette.check().is_ok());
+
+        let empty: Palette = serde_json::from_str(r#"{"name": "", "rows": [[]]}"#).unwrap();
+        assert!(empty.check().is_err());
+
+        let json = serde_json::to_string(&Palette::skribbl()).unwrap();
+        assert!(json.contains(r##"["#ffffff","#8b8b8b","#ef130b""##));
+    }
+
+    #[test]
+    fn finds_the_swatches() {
+        let color_box = Box {
+            x: 10,
+            y: 20,
+            width: 4,
+            height: 4,
+        };
+        let positions = Palette::skribbl().positions(color_box);
+
+        assert_eq!(positions.len(), 22);
+        assert_eq!(positions[&WHITE], ColorCoord { x: 12, y: 22 });
+        assert_eq!(positions[&LIGHT_RED], ColorCoord { x: 20, y: 22 });
+        assert_eq!(positions[&DARK_BROWN], ColorCoord { x: 52, y: 26 });
+    }
+}
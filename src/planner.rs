@@ -0,0 +1,302 @@
+use crate::colors::Color;
+use std::fmt;
+
+// groups with more strokes are scanned like a snake, the nearest neighbour search is quadratic
+const MAX_NEAREST: usize = 2000;
+// 2-opt only improves small groups, every pass is quadratic
+const MAX_TWO_OPT: usize = 400;
+const TWO_OPT_PASSES: usize = 4;
+
+/// Something that can be drawn from either end
+pub trait Stroke {
+    fn color(&self) -> &Color;
+    fn start(&self) -> (u32, u32);
+    fn end(&self) -> (u32, u32);
+    /// Draws it from the other end
+    fn reverse(&mut self);
+}
+
+/// What moving between strokes costs
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Cost {
+    /// Distance the mouse moves between strokes, in pixels of the image
+    pub travel: u64,
+    pub color_switches: u32,
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} pixels of travel, {} color switches",
+            self.travel, self.color_switches
+        )
+    }
+}
+
+fn distance(a: (u32, u32), b: (u32, u32)) -> f64 {
+    let (dx, dy) = (
+        f64::from(a.0) - f64::from(b.0),
+        f64::from(a.1) - f64::from(b.1),
+    );
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// Travel and color switches when the strokes are drawn in this order
+pub fn cost<'a, S, I>(strokes: I) -> Cost
+where
+    S: Stroke + 'a,
+    I: IntoIterator<Item = &'a S>,
+{
+    let mut cost = Cost::default();
+    let mut travel = 0.0;
+    let mut last: Option<&S> = None;
+
+    for stroke in strokes {
+        match last {
+            Some(last) => {
+                travel += distance(last.end(), stroke.start());
+                if last.color() != stroke.color() {
+                    cost.color_switches += 1;
+                }
+            }
+            None => cost.color_switches += 1,
+        }
+        last = Some(stroke);
+    }
+
+    cost.travel = travel.round() as u64;
+    cost
+}
+
+/// Orders the strokes of one color so the mouse moves as little as possible between them,
+/// starting at `from`. Strokes are drawn from whichever end is closer, so rows of strokes
+/// are drawn back and forth.
+pub fn order<S: Stroke>(strokes: Vec<S>, from: Option<(u32, u32)>) -> Vec<S> {
+    let from = match from.or_else(|| strokes.first().map(Stroke::start)) {
+        Some(from) => from,
+        None => return strokes,
+    };
+
+    if strokes.len() > MAX_NEAREST {
+        return serpentine(strokes);
+    }
+
+    let mut tour = nearest_neighbour(strokes, from);
+    if tour.len() <= MAX_TWO_OPT {
+        two_opt(&mut tour, from);
+    }
+
+    tour
+}
+
+// always continues with the closest end of the remaining strokes
+fn nearest_neighbour<S: Stroke>(mut strokes: Vec<S>, from: (u32, u32)) -> Vec<S> {
+    let mut tour = Vec::with_capacity(strokes.len());
+    let mut position = from;
+
+    while !strokes.is_empty() {
+        let mut best = (f64::MAX, 0, false);
+
+        for (index, stroke) in strokes.iter().enumerate() {
+            let to_start = distance(position, stroke.start());
+            let to_end = distance(position, stroke.end());
+
+            if to_start < best.0 {
+                best = (to_start, index, false);
+            }
+            if to_end < best.0 {
+                best = (to_end, index, true);
+            }
+        }
+
+        let mut stroke = strokes.swap_remove(best.1);
+        if best.2 {
+            stroke.reverse();
+        }
+        position = stroke.end();
+        tour.push(stroke);
+    }
+
+    tour
+}
+
+// reverses parts of the tour as long as that makes it shorter
+fn two_opt<S: Stroke>(tour: &mut [S], from: (u32, u32)) {
+    for _ in 0..TWO_OPT_PASSES {
+        let mut improved = false;
+
+        for first in 0..tour.len() {
+            for last in first + 1..tour.len() {
+                let before = if first == 0 {
+                    from
+                } else {
+                    tour[first - 1].end()
+                };
+                let after = tour.get(last + 1).map(Stroke::start);
+
+                // drawing the part backwards only changes the two moves at its ends
+                let old = distance(before, tour[first].start())
+                    + after.map_or(0.0, |after| distance(tour[last].end(), after));
+                let new = distance(before, tour[last].end())
+                    + after.map_or(0.0, |after| distance(tour[first].start(), after));
+
+                if new + 1e-9 < old {
+                    tour[first..=last].reverse();
+                    tour[first..=last].iter_mut().for_each(Stroke::reverse);
+                    improved = true;
+                }
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+}
+
+// rows from the top down, every other row from right to left
+fn serpentine<S: Stroke>(mut strokes: Vec<S>) -> Vec<S> {
+    let row = |stroke: &S| stroke.start().1.min(stroke.end().1);
+    let left = |stroke: &S| stroke.start().0.min(stroke.end().0);
+    strokes.sort_by_key(|stroke| (row(stroke), left(stroke)));
+
+    let mut tour = Vec::with_capacity(strokes.len());
+    let mut rows = 0;
+    while !strokes.is_empty() {
+        let y = row(&strokes[0]);
+        let count = strokes.iter().take_while(|stroke| row(stroke) == y).count();
+        let mut strokes_in_row: Vec<S> = strokes.drain(..count).collect();
+
+        let backwards = rows % 2 == 1;
+        if backwards {
+            strokes_in_row.reverse();
+        }
+        for mut stroke in strokes_in_row {
+            if (stroke.start().0 > stroke.end().0) != backwards {
+                stroke.reverse();
+            }
+            tour.push(stroke);
+        }
+
+        rows += 1;
+    }
+
+    tour
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::{BLACK, LIGHT_RED};
+
+    #[derive(PartialEq, Debug)]
+    struct Line((u32, u32), (u32, u32), Color);
+
+    impl Stroke for Line {
+        fn color(&self) -> &Color {
+            &self.2
+        }
+
+        fn start(&self) -> (u32, u32) {
+            self.0
+        }
+
+        fn end(&self) -> (u32, u32) {
+            self.1
+        }
+
+        fn reverse(&mut self) {
+            *self = Line(self.1, self.0, self.2.clone());
+        }
+    }
+
+    fn line(start: (u32, u32), end: (u32, u32)) -> Line {
+        Line(start, end, BLACK)
+    }
+
+    // rows of a rectangle, all drawn from left to right
+    fn rows(count: u32) -> Vec<Line> {
+        (0..count).map(|y| line((0, y), (9, y))).collect()
+    }
+
+    fn dots(xs: &[u32]) -> Vec<Line> {
+        xs.iter().map(|&x| line((x, 0), (x, 0))).collect()
+    }
+
+    #[test]
+    fn draws_rows_back_and_forth() {
+        let tour = order(rows(4), Some((0, 0)));
+
+        assert_eq!(
+            tour,
+            vec![
+                line((0, 0), (9, 0)),
+                line((9, 1), (0, 1)),
+                line((0, 2), (9, 2)),
+                line((9, 3), (0, 3)),
+            ]
+        );
+        assert_eq!(cost(&tour).travel, 3);
+        assert_eq!(cost(&rows(4)).travel, 3 * 9);
+    }
+
+    #[test]
+    fn visits_close_strokes_first() {
+        let tour = order(dots(&[20, 0, 21, 1]), Some((0, 0)));
+
+        assert_eq!(
+            tour.iter().map(|dot| dot.0 .0).collect::<Vec<_>>(),
+            vec![0, 1, 20, 21]
+        );
+        assert_eq!(cost(&dots(&[20, 0, 21, 1])).travel, 20 + 21 + 20);
+        assert_eq!(cost(&tour).travel, 21);
+    }
+
+    #[test]
+    fn untangles_crossing_moves() {
+        // going right first means coming back a long way
+        let mut tour = dots(&[3, 4, 8, 1]);
+
+        two_opt(&mut tour, (2, 0));
+
+        assert_eq!(
+            tour.iter().map(|dot| dot.0 .0).collect::<Vec<_>>(),
+            vec![1, 3, 4, 8]
+        );
+    }
+
+    #[test]
+    fn counts_color_switches() {
+        let mut strokes = rows(2);
+        strokes.push(Line((0, 2), (9, 2), LIGHT_RED));
+        strokes.push(line((0, 3), (9, 3)));
+
+        assert_eq!(
+            cost(&strokes),
+            Cost {
+                travel: 3 * 9,
+                color_switches: 3,
+            }
+        );
+        assert_eq!(cost(&Vec::<Line>::new()), Cost::default());
+    }
+
+    #[test]
+    fn scans_large_groups_like_a_snake() {
+        let tour = serpentine(vec![
+            line((9, 1), (5, 1)),
+            line((0, 1), (2, 1)),
+            line((4, 0), (0, 0)),
+        ]);
+
+        assert_eq!(
+            tour,
+            vec![
+                line((0, 0), (4, 0)),
+                line((9, 1), (5, 1)),
+                line((2, 1), (0, 1)),
+            ]
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{
+    color_distance::ColorDistance,
+    colors::{Color, WHITE},
+    image_converter::Image,
+    palette::Palette,
+};
+use std::collections::HashMap;
+
+// rounds of trying to swap a chosen color for a better one
+const SWAP_PASSES: usize = 4;
+
+/// Colors for games with a color picker, picked for the image instead of taken from a palette.
+/// White is always one of them, it doesn't have to be drawn on the white canvas.
+pub fn adaptive_palette(image: &Image, count: usize) -> Palette {
+    let mut colors = vec![WHITE];
+    for color in median_cut(image, count.saturating_sub(1)) {
+        if !colors.contains(&color) {
+            colors.push(color);
+        }
+    }
+
+    Palette {
+        name: format!("{} custom colors", colors.len()),
+        rows: vec![colors],
+    }
+}
+
+/// The `count` colors of the palette that keep the image closest to the original.
+/// They are added one by one by how much they lower the error, then swapped for colors
+/// that aren't chosen as long as that lowers it further. White is the canvas and always
+/// chosen if the palette has it.
+pub fn best_colors(
+    image: &Image,
+    palette: &Palette,
+    count: usize,
+    distance: ColorDistance,
+) -> Vec<Color> {
+    let colors: Vec<&Color> = palette.colors().collect();
+
+    // similar colors are counted together, photos have too many to compare all of them
+    let mut histogram: HashMap<[u8; 3], u64> = HashMap::new();
+    for pixel in image.pixels() {
+        let similar = pixel.0.map(|channel| (channel & 0xf8) | 4);
+        *histogram.entry(similar).or_insert(0) += 1;
+    }
+
+    // error of every color of the image with every palette color, weighted by its pixels
+    let palette_coordinates: Vec<[f64; 3]> = colors
+        .iter()
+        .map(|color| distance.coordinates([color.r, color.g, color.b]))
+        .collect();
+    let errors: Vec<Vec<f64>> = histogram
+        .iter()
+        .map(|(&rgb, &pixels)| {
+            let coordinates = distance.coordinates(rgb);
+            palette_coordinates
+                .iter()
+                .map(|&palette| distance.compare(coordinates, palette) * pixels as f64)
+                .collect()
+        })
+        .collect();
+    let total_error = |chosen: &[usize]| -> f64 {
+        errors
+            .iter()
+            .map(|errors| {
+                chosen
+                    .iter()
+                    .map(|&index| errors[index])
+                    .fold(f64::MAX, f64::min)
+            })
+            .sum()
+    };
+
+    let mut chosen: Vec<usize> = colors
+        .iter()
+        .position(|&color| *color == WHITE)
+        .into_iter()
+        .collect();
+    while chosen.len() < count.min(colors.len()) {
+        let best = (0..colors.len())
+            .filter(|index| !chosen.contains(index))
+            .map(|index| (index, total_error(&[chosen.as_slice(), &[index]].concat())))
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+
+        match best {
+            Some((index, _)) => chosen.push(index),
+            None => break,
+        }
+    }
+
+    let mut error = total_error(&chosen);
+    for _ in 0..SWAP_PASSES {
+        let mut improved = false;
+
+        for position in 0..chosen.len() {
+            if colors[chosen[position]] == &WHITE {
+                continue;
+            }
+
+            for candidate in 0..colors.len() {
+                if chosen.contains(&candidate) {
+                    continue;
+                }
+
+                let mut swapped = chosen.clone();
+                swapped[position] = candidate;
+
+                let swapped_error = total_error(&swapped);
+                if swapped_error < error {
+                    chosen = swapped;
+                    error = swapped_error;
+                    improved = true;
+                }
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    // in the order of the palette
+    chosen.sort_unstable();
+    chosen
+        .into_iter()
+        .map(|index| colors[index].clone())
+        .collect()
+}
+
+/// Median cut: splits the colors of the image along their widest channel until there are
+/// `count` groups and returns the average color of every group. The groups are split at
+/// the mean instead of the median, so a large area of one color isn't mixed with its neighbour.
+pub fn median_cut(image: &Image, count: usize) -> Vec<Color> {
+    let mut groups: Vec<Vec<[u8; 3]>> = vec![image.pixels().map(|pixel| pixel.0).collect()];
+
+    while groups.len() < count {
+        let widest = groups
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| (index, widest_channel(pixels)))
+            .max_by_key(|&(_, (_, range))| range);
+
+        let (index, channel) = match widest {
+            Some((index, (channel, range))) if range > 0 => (index, channel),
+            // every group has a single color
+            _ => break,
+        };
+
+        let mut pixels = groups.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        let sum: u64 = pixels.iter().map(|pixel| u64::from(pixel[channel])).sum();
+        let below_mean = pixels
+            .iter()
+            .take_while(|pixel| u64::from(pixel[channel]) * pixels.len() as u64 <= sum)
+            .count();
+        let upper = pixels.split_off(below_mean);
+
+        groups.push(pixels);
+        groups.push(upper);
+    }
+
+    let mut colors: Vec<Color> = groups
+        .iter()
+        .filter(|pixels| !pixels.is_empty())
+        .map(|pixels| average(pixels))
+        .collect();
+    colors.sort_by_key(|color| (color.brightness(), color.r, color.g, color.b));
+    colors.dedup();
+
+    colors
+}
+
+// the channel whose values are spread the most and how far
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap()
+}
+
+fn average(pixels: &[[u8; 3]]) -> Color {
+    let channel = |channel: usize| {
+        let sum: u64 = pixels.iter().map(|pixel| u64::from(pixel[channel])).sum();
+        ((sum + pixels.len() as u64 / 2) / pixels.len() as u64) as u8
+    };
+
+    Color {
+        r: channel(0),
+        g: channel(1),
+        b: channel(2),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::{BLACK, LIGHT_BLUE, LIGHT_RED};
+    use image::{ImageBuffer, Rgb};
+
+    #[test]
+    fn finds_the_colors_of_the_image() {
+        // dark blue on the left, a light orange on the right, both with a little noise
+        let image = ImageBuffer::from_fn(8, 4, |x, y| {
+            let noise = ((x + y) % 3) as u8;
+            if x < 4 {
+                Rgb([10 + noise, 20, 120 - noise])
+            } else {
+                Rgb([240 - noise, 160 + noise, 40])
+            }
+        });
+
+        let colors = median_cut(&image, 2);
+        assert_eq!(
+            colors,
+            vec![
+                Color {
+                    r: 11,
+                    g: 20,
+                    b: 119
+                },
+                Color {
+                    r: 239,
+                    g: 161,
+                    b: 40
+                },
+            ]
+        );
+
+        // there are only six different colors to split into
+        assert_eq!(median_cut(&image, 20).len(), 6);
+
+        let palette = adaptive_palette(&image, 3);
+        assert_eq!(palette.rows, vec![[vec![WHITE], colors].concat()]);
+    }
+
+    #[test]
+    fn chooses_the_colors_that_cover_most_of_the_image() {
+        // mostly black and red, the red isn't exactly the one of the palette
+        let image = ImageBuffer::from_fn(10, 10, |x, y| match (x, y) {
+            (0..=4, _) => Rgb([BLACK.r, BLACK.g, BLACK.b]),
+            (_, 0) => Rgb([LIGHT_BLUE.r, LIGHT_BLUE.g, LIGHT_BLUE.b]),
+            _ => Rgb([230, 30, 20]),
+        });
+        let palette = Palette::skribbl();
+
+        assert_eq!(
+            best_colors(&image, &palette, 3, ColorDistance::Rgb),
+            vec![WHITE, LIGHT_RED, BLACK]
+        );
+        assert_eq!(
+            best_colors(&image, &palette, 4, ColorDistance::Cielab),
+            vec![WHITE, LIGHT_RED, LIGHT_BLUE, BLACK]
+        );
+        assert_eq!(
+            best_colors(&image, &palette, 1, ColorDistance::Rgb),
+            vec![WHITE]
+        );
+    }
+}
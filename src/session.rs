@@ -0,0 +1,194 @@
+use crate::{drawer::Summary, image_converter::Image, settings::Settings};
+use image::ImageBuffer;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    cell::Cell,
+    fs, io,
+    time::{Duration, Instant},
+};
+
+// next to the settings
+pub const FILENAME: &str = "skribbl_session.json";
+// the steps that are done, written while drawing instead of the whole session
+const PROGRESS_FILENAME: &str = "skribbl_session_progress.json";
+// the drawing is repeated from the last save, so a few strokes are drawn twice at most
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A drawing that can be continued after it was cancelled or the bot crashed
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    /// The settings the drawing was started with, the strokes are planned from them again
+    pub settings: Settings,
+    width: u32,
+    height: u32,
+    // the converted image as RGB bytes
+    pixels: Vec<u8>,
+    /// Steps of the drawer that are done
+    pub done: usize,
+}
+
+impl Session {
+    pub fn new(settings: &Settings, image: &Image) -> Session {
+        Session {
+            settings: settings.clone(),
+            width: image.width(),
+            height: image.height(),
+            pixels: image.as_raw().clone(),
+            done: 0,
+        }
+    }
+
+    /// The last session, None if there is none
+    pub fn load() -> Result<Option<Session>, String> {
+        let mut session: Session = match read(FILENAME)? {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+
+        if let Some(Progress { done }) = read(PROGRESS_FILENAME)? {
+            session.done = done;
+        }
+
+        Ok(Some(session))
+    }
+
+    /// Writes the settings and the image, the progress is saved on its own while drawing
+    pub fn save(&self) -> io::Result<()> {
+        // the progress of an older session doesn't belong to this one
+        remove(PROGRESS_FILENAME)?;
+
+        write(FILENAME, self)
+    }
+
+    /// Forgets the session once the drawing is finished
+    pub fn remove() -> io::Result<()> {
+        remove(PROGRESS_FILENAME)?;
+        remove(FILENAME)
+    }
+
+    /// The image that is drawn
+    pub fn image(&self) -> Result<Image, String> {
+        ImageBuffer::from_raw(self.width, self.height, self.pixels.clone())
+            .ok_or_else(|| format!("The image in {} is damaged", FILENAME))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    done: usize,
+}
+
+// None if the file doesn't exist
+fn read<T: DeserializeOwned>(filename: &str) -> Result<Option<T>, String> {
+    let content = match fs::read_to_string(filename) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("Failed to read {}: {}", filename, err)),
+    };
+
+    serde_json::from_str(&content)
+        .map(Some)
+        .map_err(|err| format!("Failed to read {}: {}", filename, err))
+}
+
+// writes next to the file and renames it, a crash while writing keeps the old file
+fn write<T: Serialize>(filename: &str, value: &T) -> io::Result<()> {
+    let temporary = format!("{}.tmp", filename);
+
+    fs::write(&temporary, serde_json::to_string(value).unwrap())?;
+    fs::rename(&temporary, filename)
+}
+
+fn remove(filename: &str) -> io::Result<()> {
+    match fs::remove_file(filename) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Saves the session when the drawing starts and its progress while drawing
+pub struct Recorder {
+    done: Cell<usize>,
+    last_save: Cell<Instant>,
+}
+
+impl Recorder {
+    pub fn start(session: Session) -> Recorder {
+        if let Err(err) = session.save() {
+            println!("Failed to write {}: {}", FILENAME, err);
+        }
+
+        Recorder {
+            done: Cell::new(session.done),
+            last_save: Cell::new(Instant::now()),
+        }
+    }
+
+    /// Remembers the steps that are done, the file is only written now and then
+    pub fn record(&self, done: usize) {
+        self.done.set(done);
+
+        if self.last_save.get().elapsed() >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    /// Removes the session if everything was drawn, keeps it for resuming otherwise
+    pub fn finish(&self, summary: &Summary) {
+        if summary.done >= summary.total {
+            if let Err(err) = Session::remove() {
+                println!("Failed to remove {}: {}", FILENAME, err);
+            }
+        } else {
+            self.done.set(summary.steps);
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        self.last_save.set(Instant::now());
+
+        let progress = Progress {
+            done: self.done.get(),
+        };
+        if let Err(err) = write(PROGRESS_FILENAME, &progress) {
+            println!("Failed to write {}: {}", PROGRESS_FILENAME, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn stores_the_image() {
+        let image = ImageBuffer::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 7]));
+        let mut session = Session::new(&Settings::default(), &image);
+        session.done = 5;
+
+        let json = serde_json::to_string(&session).unwrap();
+        let loaded: Session = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(loaded.image().unwrap(), image);
+        assert_eq!(loaded.done, 5);
+    }
+
+    #[test]
+    fn replaces_files_in_one_go() {
+        let path =
+            std::env::temp_dir().join(format!("skribbl_progress_{}.json", std::process::id()));
+        let filename = path.to_str().unwrap();
+
+        write(filename, &Progress { done: 3 }).unwrap();
+        write(filename, &Progress { done: 8 }).unwrap();
+        let progress: Option<Progress> = read(filename).unwrap();
+
+        assert_eq!(progress.map(|progress| progress.done), Some(8));
+        assert!(!std::path::Path::new(&format!("{}.tmp", filename)).exists());
+
+        remove(filename).unwrap();
+        assert!(read::<Progress>(filename).unwrap().is_none());
+    }
+}
@@ -1,12 +1,27 @@
-use crate::gui::GTK;
+use crate::{
+    brushes::Brush,
+    color_distance::ColorDistance,
+    drawer::{Box, ColorPicker, DrawOrder, PointerGuard, Strokes, Tools},
+    game::{Profile, BUILTIN},
+    gui::GTK,
+    hotkeys::{Hotkey, Hotkeys},
+    image_converter::{Dithering, Sketch},
+    palette::Palette,
+};
 use gtk::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     fs::{self, OpenOptions},
     io::{self, Read},
+    sync::{Mutex, OnceLock},
+    time::{Duration, SystemTime},
 };
 
-const FILENAME: &str = "skribbl_settings.json";
+pub const FILENAME: &str = "skribbl_settings.json";
+
+// profiles and palettes that were read, by name and when their file was changed
+type Loaded<T> = HashMap<String, (Option<SystemTime>, T)>;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Settings {
@@ -21,11 +36,44 @@ pub struct Settings {
     pub delay: f64,
     pub scale: f64,
     pub step: Option<f64>,
+    // replaced by dithering, still read from old settings
     pub dither: bool,
     pub checkerboard: bool,
     // we need to annotate every new setting with this
     // for it to be able to load old settings
     pub grayscale: Option<bool>,
+    pub strokes: Option<Strokes>,
+    pub order: Option<DrawOrder>,
+    pub sketch: Option<Sketch>,
+    pub dithering: Option<Dithering>,
+    pub color_distance: Option<ColorDistance>,
+    pub fill: Option<bool>,
+    // centers of the brush and bucket buttons
+    pub brush_tool_x: Option<u32>,
+    pub brush_tool_y: Option<u32>,
+    pub fill_tool_x: Option<u32>,
+    pub fill_tool_y: Option<u32>,
+    pub brushes: Option<Vec<Brush>>,
+    // seconds, 0 for no limit
+    pub time_budget: Option<f64>,
+    pub hotkeys: Option<Hotkeys>,
+    pub pointer_guard: Option<PointerGuard>,
+    // strokes are grouped by color and ordered to keep the mouse paths short
+    pub optimize: Option<bool>,
+    // the canvas is captured after drawing and pixels that were missed are drawn again
+    pub verify: Option<bool>,
+    // JSON file with the colors of the game, the colors of the game profile if None
+    pub palette: Option<String>,
+    // id of a built-in game profile or a JSON file with the layout of another game,
+    // palette and buttons that aren't entered come from it
+    pub profile: Option<String>,
+    // colors picked for every image if the game has a color picker, 0 to use the palette
+    pub custom_colors: Option<u32>,
+    // the best colors of the palette for every image, 0 for all of them
+    pub max_colors: Option<u32>,
+    // only needed for the uinput backend, there's no way to query them without X
+    pub screen_width: Option<u32>,
+    pub screen_height: Option<u32>,
 }
 
 impl Settings {
@@ -55,6 +103,153 @@ impl Settings {
         fs::write(FILENAME, &content)
     }
 
+    /// Whether all positions have been entered, the tools are only needed for filling
+    pub fn is_ready(&self) -> bool {
+        self.drawing_x != 0
+            && self.drawing_y != 0
+            && self.drawing_width != 0
+            && self.drawing_height != 0
+            && self.color_x != 0
+            && self.color_y != 0
+            && self.color_width != 0
+            && self.color_height != 0
+            && (!self.fill.unwrap_or(false) || self.tools().is_some())
+    }
+
+    pub fn dithering(&self) -> Dithering {
+        match self.dithering {
+            Some(dithering) => dithering,
+            None if self.dither => Dithering::FloydSteinberg,
+            None => Dithering::None,
+        }
+    }
+
+    pub fn sketch(&self) -> Sketch {
+        self.sketch
+            .unwrap_or_else(|| Settings::default().sketch.unwrap())
+    }
+
+    pub fn pointer_guard(&self) -> PointerGuard {
+        self.pointer_guard
+            .unwrap_or_else(|| Settings::default().pointer_guard.unwrap())
+    }
+
+    /// The built-in game profile or the one from the profile file, skribbl.io's if there is none
+    pub fn profile(&self) -> Result<Profile, String> {
+        static PROFILES: OnceLock<Mutex<Loaded<Profile>>> = OnceLock::new();
+
+        match &self.profile {
+            Some(name) => cached(&PROFILES, name, Profile::find),
+            None => Ok(Profile::skribbl()),
+        }
+    }
+
+    /// The palette from the palette file, the game's if there is none
+    pub fn palette(&self) -> Result<Palette, String> {
+        static PALETTES: OnceLock<Mutex<Loaded<Palette>>> = OnceLock::new();
+
+        match &self.palette {
+            Some(path) => cached(&PALETTES, path, Palette::load),
+            None => self.profile().map(|profile| profile.palette),
+        }
+    }
+
+    /// Err if the profile or palette file can't be read. Drawings check this first,
+    /// the positions and colors of skribbl.io are used for them otherwise.
+    pub fn check_files(&self) -> Result<(), String> {
+        self.profile()?;
+        self.palette()?;
+        Ok(())
+    }
+
+    pub fn hotkeys(&self) -> Hotkeys {
+        self.hotkeys.clone().unwrap_or_default()
+    }
+
+    /// How long a drawing may take, None if there's no limit
+    pub fn time_budget(&self) -> Option<Duration> {
+        match self.time_budget {
+            Some(seconds) if seconds > 0.0 => Some(Duration::from_secs_f64(seconds)),
+            _ => None,
+        }
+    }
+
+    /// Positions of the tool buttons, the game's if one of them is missing
+    pub fn tools(&self) -> Option<Tools> {
+        let entered = || {
+            Some(Tools {
+                brush: (self.brush_tool_x?, self.brush_tool_y?),
+                bucket: (self.fill_tool_x?, self.fill_tool_y?),
+            })
+        };
+
+        entered().or_else(|| self.profile().ok()?.tools(self.color_box()))
+    }
+
+    /// The brush size buttons, the game's if none were entered
+    pub fn brushes(&self) -> Option<Vec<Brush>> {
+        self.brushes.clone().or_else(|| {
+            Some(self.profile().ok()?.brushes(self.color_box()))
+                .filter(|brushes| !brushes.is_empty())
+        })
+    }
+
+    /// The color picker of the game, None if colors are picked by clicking their swatch
+    pub fn color_picker(&self) -> Option<ColorPicker> {
+        self.profile().ok()?.color_picker(self.color_box())
+    }
+
+    /// How many colors of the palette an image may use, None if it may use all of them
+    pub fn max_colors(&self) -> Option<usize> {
+        match self.max_colors {
+            Some(count) if count > 0 => Some(count as usize),
+            _ => None,
+        }
+    }
+
+    /// How many colors are picked for every image, None if the palette is used
+    pub fn custom_colors(&self) -> Option<usize> {
+        match self.custom_colors {
+            // the color maps hold at most 256 colors, white is one of them
+            Some(count) if count > 0 && self.color_picker().is_some() => {
+                Some((count as usize).min(256))
+            }
+            _ => None,
+        }
+    }
+
+    /// Screen pixels per pixel of the image, as large as the smallest brush if there are any
+    pub fn pixel_size(&self) -> f64 {
+        match self
+            .brushes()
+            .and_then(|brushes| brushes.iter().map(|brush| brush.size).min())
+        {
+            Some(size) => f64::from(size),
+            None => self
+                .step
+                .unwrap_or_else(|| Settings::default().step.unwrap()),
+        }
+    }
+
+    pub fn drawing_area(&self) -> Box {
+        Box {
+            x: self.drawing_x,
+            y: self.drawing_y,
+            width: self.drawing_width,
+            height: self.drawing_height,
+        }
+    }
+
+    /// The white box of the color palette
+    pub fn color_box(&self) -> Box {
+        Box {
+            x: self.color_x,
+            y: self.color_y,
+            width: self.color_width,
+            height: self.color_height,
+        }
+    }
+
     pub fn save_to_gtk(&mut self, gtk: GTK) {
         if self.drawing_x != 0 {
             gtk.drawing_x.set_text(&self.drawing_x.to_string());
@@ -83,6 +278,30 @@ impl Settings {
             gtk.color_height.set_text(&self.color_height.to_string());
         }
 
+        let tool_entries = [
+            (&gtk.brush_tool_x, self.brush_tool_x),
+            (&gtk.brush_tool_y, self.brush_tool_y),
+            (&gtk.fill_tool_x, self.fill_tool_x),
+            (&gtk.fill_tool_y, self.fill_tool_y),
+        ];
+        for (entry, value) in tool_entries.iter() {
+            if let Some(value) = value {
+                entry.set_text(&value.to_string());
+            }
+        }
+        if let Some(brushes) = &self.brushes {
+            gtk.brushes.set_text(&Brush::format_list(brushes));
+        }
+
+        let hotkeys = self.hotkeys();
+        gtk.hotkey_draw_clipboard
+            .set_text(&hotkeys.draw_clipboard.to_string());
+        gtk.hotkey_pause.set_text(&hotkeys.pause.to_string());
+        gtk.hotkey_resume.set_text(&hotkeys.resume.to_string());
+        gtk.hotkey_abort.set_text(&hotkeys.abort.to_string());
+        gtk.hotkey_next_result
+            .set_text(&hotkeys.next_result.to_string());
+
         gtk.delay.set_value(self.delay);
         gtk.scale.set_value(self.scale);
         gtk.step.set_value(
@@ -90,12 +309,63 @@ impl Settings {
                 .unwrap_or_else(|| Settings::default().step.unwrap()),
         );
 
-        gtk.dither.set_active(self.dither);
+        gtk.custom_colors.set_value(f64::from(
+            self.custom_colors
+                .unwrap_or_else(|| Settings::default().custom_colors.unwrap()),
+        ));
+
+        gtk.max_colors.set_value(f64::from(
+            self.max_colors
+                .unwrap_or_else(|| Settings::default().max_colors.unwrap()),
+        ));
+
+        gtk.time_budget.set_value(
+            self.time_budget
+                .unwrap_or_else(|| Settings::default().time_budget.unwrap()),
+        );
+
+        gtk.dithering.set_active_id(Some(self.dithering().id()));
+        gtk.color_distance.set_active_id(Some(
+            self.color_distance
+                .unwrap_or_else(|| Settings::default().color_distance.unwrap())
+                .id(),
+        ));
+        gtk.sketch.set_active_id(Some(self.sketch().id()));
+        gtk.pointer_guard
+            .set_active_id(Some(self.pointer_guard().id()));
+        // profile files are added to the built-in games
+        let profile = self.profile.as_deref().unwrap_or(BUILTIN[0]);
+        if !gtk.game.set_active_id(Some(profile)) {
+            gtk.game.append(Some(profile), profile);
+            gtk.game.set_active_id(Some(profile));
+        }
         gtk.checkerboard.set_active(self.checkerboard);
         gtk.grayscale.set_active(
             self.grayscale
                 .unwrap_or_else(|| Settings::default().grayscale.unwrap()),
         );
+        gtk.fill.set_active(
+            self.fill
+                .unwrap_or_else(|| Settings::default().fill.unwrap()),
+        );
+        gtk.optimize.set_active(
+            self.optimize
+                .unwrap_or_else(|| Settings::default().optimize.unwrap()),
+        );
+        gtk.verify.set_active(
+            self.verify
+                .unwrap_or_else(|| Settings::default().verify.unwrap()),
+        );
+        gtk.strokes.set_active_id(Some(
+            self.strokes
+                .unwrap_or_else(|| Settings::default().strokes.unwrap())
+                .id(),
+        ));
+        gtk.order.set_active_id(Some(
+            self.order
+                .unwrap_or_else(|| Settings::default().order.unwrap())
+                .id(),
+        ));
     }
 
     pub fn load_from_gtk(&mut self, gtk: GTK) {
@@ -157,12 +427,93 @@ impl Settings {
             .parse()
             .unwrap_or(0);
 
+        let tool_position =
+            |entry: &gtk::Entry| entry.get_text().and_then(|text| text.as_str().parse().ok());
+        self.brush_tool_x = tool_position(&gtk.brush_tool_x);
+        self.brush_tool_y = tool_position(&gtk.brush_tool_y);
+        self.fill_tool_x = tool_position(&gtk.fill_tool_x);
+        self.fill_tool_y = tool_position(&gtk.fill_tool_y);
+        self.brushes = gtk
+            .brushes
+            .get_text()
+            .and_then(|text| Brush::parse_list(text.as_str()).ok())
+            .filter(|brushes| !brushes.is_empty());
+
+        // keys that can't be read stay as they were
+        let hotkeys = self.hotkeys();
+        let hotkey = |entry: &gtk::Entry, current: &Hotkey| {
+            entry
+                .get_text()
+                .and_then(|text| Hotkey::parse(text.as_str()).ok())
+                .unwrap_or_else(|| current.clone())
+        };
+        self.hotkeys = Some(Hotkeys {
+            draw_clipboard: hotkey(&gtk.hotkey_draw_clipboard, &hotkeys.draw_clipboard),
+            pause: hotkey(&gtk.hotkey_pause, &hotkeys.pause),
+            resume: hotkey(&gtk.hotkey_resume, &hotkeys.resume),
+            abort: hotkey(&gtk.hotkey_abort, &hotkeys.abort),
+            next_result: hotkey(&gtk.hotkey_next_result, &hotkeys.next_result),
+        });
+
         self.delay = gtk.delay.get_value();
         self.scale = gtk.scale.get_value();
         self.step = Some(gtk.step.get_value());
-        self.dither = gtk.dither.get_active();
+        self.time_budget = Some(gtk.time_budget.get_value());
+        self.custom_colors = Some(gtk.custom_colors.get_value() as u32);
+        self.max_colors = Some(gtk.max_colors.get_value() as u32);
+        self.dithering = gtk
+            .dithering
+            .get_active_id()
+            .and_then(|id| Dithering::from_id(id.as_str()));
+        self.dither = self.dithering() != Dithering::None;
+        self.color_distance = gtk
+            .color_distance
+            .get_active_id()
+            .and_then(|id| ColorDistance::from_id(id.as_str()));
+        self.sketch = gtk
+            .sketch
+            .get_active_id()
+            .and_then(|id| Sketch::from_id(id.as_str()));
+        self.pointer_guard = gtk
+            .pointer_guard
+            .get_active_id()
+            .and_then(|id| PointerGuard::from_id(id.as_str()));
+        self.profile = gtk.game.get_active_id().map(String::from);
         self.checkerboard = gtk.checkerboard.get_active();
+        self.fill = Some(gtk.fill.get_active());
+        self.optimize = Some(gtk.optimize.get_active());
+        self.verify = Some(gtk.verify.get_active());
         self.grayscale = Some(gtk.grayscale.get_active());
+        self.strokes = gtk
+            .strokes
+            .get_active_id()
+            .and_then(|id| Strokes::from_id(id.as_str()));
+        self.order = gtk
+            .order
+            .get_active_id()
+            .and_then(|id| DrawOrder::from_id(id.as_str()));
+    }
+}
+
+// reads the file only the first time and again when it was changed
+fn cached<T: Clone>(
+    cache: &OnceLock<Mutex<Loaded<T>>>,
+    name: &str,
+    load: fn(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    // built-in profiles have no file and never change
+    let modified = fs::metadata(name)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let mut cache = cache.get_or_init(Default::default).lock().unwrap();
+
+    match cache.get(name) {
+        Some((loaded, value)) if *loaded == modified => Ok(value.clone()),
+        _ => {
+            let value = load(name)?;
+            cache.insert(name.into(), (modified, value.clone()));
+            Ok(value)
+        }
     }
 }
 
@@ -183,6 +534,71 @@ impl Default for Settings {
             dither: true,
             checkerboard: true,
             grayscale: Some(false),
+            strokes: Some(Strokes::Rows),
+            order: Some(DrawOrder::Brightness),
+            sketch: Some(Sketch::Off),
+            dithering: Some(Dithering::FloydSteinberg),
+            color_distance: Some(ColorDistance::Rgb),
+            fill: Some(false),
+            brush_tool_x: None,
+            brush_tool_y: None,
+            fill_tool_x: None,
+            fill_tool_y: None,
+            brushes: None,
+            time_budget: Some(0.0),
+            hotkeys: Some(Hotkeys::default()),
+            pointer_guard: Some(PointerGuard::Pause),
+            optimize: Some(true),
+            verify: Some(false),
+            palette: None,
+            profile: None,
+            custom_colors: Some(0),
+            max_colors: Some(0),
+            screen_width: None,
+            screen_height: None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::tests::{profile_file, PICKER_PROFILE};
+
+    #[test]
+    fn reports_profiles_that_cant_be_read() {
+        let mut settings = Settings {
+            color_width: 36,
+            color_height: 36,
+            profile: Some("no such game".into()),
+            ..Settings::default()
+        };
+
+        assert!(settings.profile().is_err());
+        assert!(settings.palette().is_err());
+        assert!(settings.check_files().is_err());
+        // the buttons of skribbl.io aren't used instead
+        assert!(settings.color_picker().is_none());
+
+        settings.profile = Some("garticphone".into());
+        assert!(settings.check_files().is_ok());
+        assert!(settings.color_picker().is_some());
+    }
+
+    #[test]
+    fn reads_profile_files_again_when_they_change() {
+        let path = profile_file("settings", PICKER_PROFILE);
+        let settings = Settings {
+            profile: Some(path.clone()),
+            ..Settings::default()
+        };
+        assert_eq!(settings.palette().unwrap().name, "test");
+
+        // a broken file is reported instead of the profile that was read before
+        fs::write(&path, "{").unwrap();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        assert!(settings.profile().is_err());
+    }
+}
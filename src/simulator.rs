@@ -0,0 +1,567 @@
+use crate::{
+    brushes::Brush,
+    colors::{Color, WHITE},
+    desktop::Desktop,
+    drawer::{Box, ColorPicker, Drawer, Tools},
+    image_converter::Image,
+    palette::Palette,
+    settings::Settings,
+};
+use image::{ImageBuffer, Rgb};
+use std::{
+    fmt,
+    ops::AddAssign,
+    sync::{atomic::AtomicBool, Arc, Mutex},
+    time::Duration,
+};
+
+// the game starts with the smallest brush
+const BRUSH_SIZE: u32 = 3;
+
+/// What a drawing costs
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct Stats {
+    /// Presses on the canvas, every stroke starts with one
+    pub clicks: u32,
+    /// Presses that were dragged over more than one position
+    pub strokes: u32,
+    pub color_switches: u32,
+    /// Distance in screen pixels the mouse moved over the canvas between strokes
+    pub travel: u64,
+    /// Sum of all delays the drawer waited for
+    pub estimated_time: Duration,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.estimated_time.as_secs();
+
+        write!(
+            f,
+            "{} clicks, {} strokes, {} color switches, {} pixels of travel, about {}m {}s",
+            self.clicks,
+            self.strokes,
+            self.color_switches,
+            self.travel,
+            seconds / 60,
+            seconds % 60
+        )
+    }
+}
+
+impl AddAssign for Stats {
+    fn add_assign(&mut self, other: Stats) {
+        self.clicks += other.clicks;
+        self.strokes += other.strokes;
+        self.color_switches += other.color_switches;
+        self.travel += other.travel;
+        self.estimated_time += other.estimated_time;
+    }
+}
+
+struct State {
+    canvas: Image,
+    cursor: (u32, u32),
+    pressed: bool,
+    dragged: bool,
+    color: Option<Color>,
+    bucket: bool,
+    brush_size: u32,
+    // typed into the entry of the color picker
+    typed: String,
+    stats: Stats,
+}
+
+/// Paints on an image instead of the screen
+pub struct SimulatedDesktop {
+    drawing_area: Box,
+    color_box: Box,
+    palette: Palette,
+    tools: Option<Tools>,
+    brushes: Vec<Brush>,
+    picker: Option<ColorPicker>,
+    state: Mutex<State>,
+}
+
+impl SimulatedDesktop {
+    pub fn new(drawing_area: Box, color_box: Box) -> Self {
+        let canvas = ImageBuffer::from_pixel(
+            drawing_area.width,
+            drawing_area.height,
+            Rgb([WHITE.r, WHITE.g, WHITE.b]),
+        );
+
+        SimulatedDesktop {
+            drawing_area,
+            color_box,
+            palette: Palette::default(),
+            tools: None,
+            brushes: Vec::new(),
+            picker: None,
+            state: Mutex::new(State {
+                canvas,
+                cursor: (0, 0),
+                pressed: false,
+                dragged: false,
+                color: None,
+                bucket: false,
+                brush_size: BRUSH_SIZE,
+                typed: String::new(),
+                stats: Stats::default(),
+            }),
+        }
+    }
+
+    /// Lays out the swatches of another palette than skribbl.io's
+    pub fn with_palette(mut self, palette: Palette) -> Self {
+        self.palette = palette;
+        self
+    }
+
+    /// Lets the brush and bucket buttons switch tools
+    pub fn with_tools(mut self, tools: Tools) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Lets the brush size buttons change the size of the brush
+    pub fn with_brushes(mut self, brushes: Vec<Brush>) -> Self {
+        self.brushes = brushes;
+        self
+    }
+
+    /// Takes colors typed into the entry of the color picker
+    pub fn with_color_picker(mut self, picker: ColorPicker) -> Self {
+        self.picker = Some(picker);
+        self
+    }
+
+    /// What the canvas looks like now
+    pub fn canvas(&self) -> Image {
+        self.state.lock().unwrap().canvas.clone()
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.state.lock().unwrap().stats
+    }
+
+    // swatch below a screen position
+    fn color_at(&self, x: u32, y: u32) -> Option<Color> {
+        if x < self.color_box.x || y < self.color_box.y {
+            return None;
+        }
+
+        let column = ((x - self.color_box.x) / self.color_box.width) as usize;
+        let row = ((y - self.color_box.y) / self.color_box.height) as usize;
+
+        self.palette.get(column, row).cloned()
+    }
+
+    // paints the round brush centered on a screen position
+    fn paint(&self, state: &mut State, x: u32, y: u32) {
+        let color = match &state.color {
+            Some(color) => Rgb([color.r, color.g, color.b]),
+            None => return,
+        };
+
+        let size = state.brush_size;
+        let radius = size / 2;
+        for brush_y in y.saturating_sub(radius)..=y + radius {
+            for brush_x in x.saturating_sub(radius)..=x + radius {
+                if brush_x < self.drawing_area.x || brush_y < self.drawing_area.y {
+                    continue;
+                }
+
+                // a pixel is painted if it's at most half the size away from the center
+                let (dx, dy) = (
+                    i64::from(brush_x) - i64::from(x),
+                    i64::from(brush_y) - i64::from(y),
+                );
+                if 4 * (dx * dx + dy * dy) > i64::from(size * size) {
+                    continue;
+                }
+
+                let canvas_x = brush_x - self.drawing_area.x;
+                let canvas_y = brush_y - self.drawing_area.y;
+                if canvas_x < state.canvas.width() && canvas_y < state.canvas.height() {
+                    state.canvas.put_pixel(canvas_x, canvas_y, color);
+                }
+            }
+        }
+    }
+
+    // the bucket fills all connected pixels that have the same color
+    fn fill(&self, state: &mut State, x: u32, y: u32) {
+        let color = match &state.color {
+            Some(color) => Rgb([color.r, color.g, color.b]),
+            None => return,
+        };
+
+        let (width, height) = state.canvas.dimensions();
+        let start = (x - self.drawing_area.x, y - self.drawing_area.y);
+        let target = *state.canvas.get_pixel(start.0, start.1);
+        if target == color {
+            return;
+        }
+
+        let mut stack = vec![start];
+        state.canvas.put_pixel(start.0, start.1, color);
+
+        while let Some((x, y)) = stack.pop() {
+            let neighbours = [
+                (x.wrapping_sub(1), y),
+                (x + 1, y),
+                (x, y.wrapping_sub(1)),
+                (x, y + 1),
+            ];
+
+            for &(nx, ny) in neighbours.iter() {
+                if nx < width && ny < height && *state.canvas.get_pixel(nx, ny) == target {
+                    state.canvas.put_pixel(nx, ny, color);
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+
+    // whether a screen position is on a button centered on (button_x, button_y),
+    // the buttons are about as big as the color swatches
+    fn on_button(&self, (x, y): (u32, u32), (button_x, button_y): (u32, u32)) -> bool {
+        (i64::from(x) - i64::from(button_x)).abs() <= i64::from(self.color_box.width / 2)
+            && (i64::from(y) - i64::from(button_y)).abs() <= i64::from(self.color_box.height / 2)
+    }
+
+    // tool button below a screen position, true for the bucket
+    fn tool_at(&self, x: u32, y: u32) -> Option<bool> {
+        let tools = self.tools?;
+        let hits = |tool| self.on_button((x, y), tool);
+
+        if hits(tools.bucket) {
+            Some(true)
+        } else if hits(tools.brush) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    fn on_canvas(&self, x: u32, y: u32) -> bool {
+        x >= self.drawing_area.x
+            && y >= self.drawing_area.y
+            && x < self.drawing_area.x + self.drawing_area.width
+            && y < self.drawing_area.y + self.drawing_area.height
+    }
+}
+
+impl Desktop for SimulatedDesktop {
+    fn move_cursor(&self, x: u32, y: u32) {
+        let mut state = self.state.lock().unwrap();
+        let (start_x, start_y) = state.cursor;
+        state.cursor = (x, y);
+
+        let (dx, dy) = (
+            i64::from(x) - i64::from(start_x),
+            i64::from(y) - i64::from(start_y),
+        );
+
+        if !state.pressed && self.on_canvas(start_x, start_y) && self.on_canvas(x, y) {
+            state.stats.travel += ((dx * dx + dy * dy) as f64).sqrt().round() as u64;
+        }
+
+        if !state.pressed || state.bucket || !self.on_canvas(start_x, start_y) {
+            return;
+        }
+
+        // the game connects two positions with a line while the button is held
+        let steps = dx.abs().max(dy.abs()).max(1);
+        for step in 1..=steps {
+            let line_x = i64::from(start_x) + dx * step / steps;
+            let line_y = i64::from(start_y) + dy * step / steps;
+            self.paint(&mut state, line_x as u32, line_y as u32);
+        }
+
+        state.dragged = true;
+    }
+
+    fn press(&self) {
+        let mut state = self.state.lock().unwrap();
+        let (x, y) = state.cursor;
+        state.pressed = true;
+        state.dragged = false;
+
+        if self.on_canvas(x, y) {
+            state.stats.clicks += 1;
+
+            if state.bucket {
+                self.fill(&mut state, x, y);
+            } else {
+                self.paint(&mut state, x, y);
+            }
+        } else if self
+            .picker
+            .is_some_and(|picker| self.on_button((x, y), picker.entry))
+        {
+            state.typed.clear();
+        } else if let Some(bucket) = self.tool_at(x, y) {
+            state.bucket = bucket;
+        } else if let Some(brush) = self
+            .brushes
+            .iter()
+            .find(|brush| self.on_button((x, y), (brush.x, brush.y)))
+        {
+            state.brush_size = brush.size;
+        } else if let Some(color) = self.color_at(x, y) {
+            if state.color.as_ref() != Some(&color) {
+                state.stats.color_switches += 1;
+                state.color = Some(color);
+            }
+        }
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.pressed && state.dragged {
+            state.stats.strokes += 1;
+        }
+        state.pressed = false;
+    }
+
+    fn type_key(&self, key: char) {
+        let mut state = self.state.lock().unwrap();
+
+        if key != '\n' {
+            state.typed.push(key);
+            return;
+        }
+
+        // the picker takes the color when Return is pressed
+        if let Ok(color) = Color::parse(&state.typed) {
+            if state.color.as_ref() != Some(&color) {
+                state.stats.color_switches += 1;
+                state.color = Some(color);
+            }
+        }
+        state.typed.clear();
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.state.lock().unwrap().stats.estimated_time += duration;
+    }
+
+    // only the canvas is simulated, the rest of the screen is white
+    fn capture_area(&self, area: Box) -> Result<Image, String> {
+        let state = self.state.lock().unwrap();
+
+        Ok(ImageBuffer::from_fn(area.width, area.height, |x, y| {
+            let (screen_x, screen_y) = (area.x + x, area.y + y);
+            if self.on_canvas(screen_x, screen_y) {
+                *state.canvas.get_pixel(
+                    screen_x - self.drawing_area.x,
+                    screen_y - self.drawing_area.y,
+                )
+            } else {
+                Rgb([WHITE.r, WHITE.g, WHITE.b])
+            }
+        }))
+    }
+}
+
+/// Draws the image on a simulated canvas with the given settings,
+/// without touching the mouse or waiting
+pub fn simulate(settings: &Settings, image: &Image) -> (Image, Stats) {
+    let mut desktop = SimulatedDesktop::new(settings.drawing_area(), settings.color_box())
+        .with_palette(settings.palette().unwrap_or_default());
+    if let Some(tools) = settings.tools() {
+        desktop = desktop.with_tools(tools);
+    }
+    if let Some(brushes) = settings.brushes() {
+        desktop = desktop.with_brushes(brushes);
+    }
+    if let Some(picker) = settings.color_picker() {
+        desktop = desktop.with_color_picker(picker);
+    }
+
+    let mut drawer = Drawer::from_settings(settings);
+    drawer.draw(&desktop, image, Arc::new(AtomicBool::new(true)));
+
+    (desktop.canvas(), desktop.stats())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        colors::*,
+        drawer::Strokes,
+        game::tests::{profile_file, PICKER_PROFILE},
+    };
+
+    fn settings(checkerboard: bool, strokes: Strokes) -> Settings {
+        Settings {
+            drawing_x: 100,
+            drawing_y: 200,
+            drawing_width: 12,
+            drawing_height: 6,
+            color_x: 10,
+            color_y: 20,
+            color_width: 4,
+            color_height: 4,
+            checkerboard,
+            strokes: Some(strokes),
+            delay: 5.0,
+            ..Settings::default()
+        }
+    }
+
+    fn image(rows: &[&[Color]]) -> Image {
+        ImageBuffer::from_fn(rows[0].len() as u32, rows.len() as u32, |x, y| {
+            let color = &rows[y as usize][x as usize];
+            Rgb([color.r, color.g, color.b])
+        })
+    }
+
+    fn rgb(color: &Color) -> Rgb<u8> {
+        Rgb([color.r, color.g, color.b])
+    }
+
+    #[test]
+    fn paints_pixels_with_the_brush() {
+        let image = image(&[
+            &[BLACK, WHITE, LIGHT_RED, WHITE],
+            &[WHITE, WHITE, WHITE, WHITE],
+        ]);
+
+        let (canvas, stats) = simulate(&settings(false, Strokes::Pixels), &image);
+
+        // every pixel covers a 3x3 block on the canvas
+        for y in 0..3 {
+            for x in 0..3 {
+                assert_eq!(*canvas.get_pixel(x, y), rgb(&BLACK));
+                assert_eq!(*canvas.get_pixel(x + 3, y), rgb(&WHITE));
+                assert_eq!(*canvas.get_pixel(x + 6, y), rgb(&LIGHT_RED));
+                assert_eq!(*canvas.get_pixel(x, y + 3), rgb(&WHITE));
+            }
+        }
+
+        assert_eq!(
+            stats,
+            Stats {
+                clicks: 2,
+                strokes: 0,
+                color_switches: 2,
+                travel: 0,
+                estimated_time: Duration::from_millis(4 * 5),
+            }
+        );
+    }
+
+    #[test]
+    fn takes_colors_from_the_picker() {
+        let settings = Settings {
+            drawing_x: 300,
+            drawing_y: 400,
+            drawing_width: 12,
+            drawing_height: 4,
+            color_x: 10,
+            color_y: 100,
+            color_width: 36,
+            color_height: 36,
+            profile: Some(profile_file("simulator", PICKER_PROFILE)),
+            ..settings(false, Strokes::Rows)
+        };
+        let red = Color::parse("#ff0013").unwrap();
+
+        // the smallest brush paints 4x4 pixels
+        let (canvas, stats) = simulate(&settings, &image(&[&[BLACK, WHITE, red.clone()]]));
+
+        assert_eq!(*canvas.get_pixel(1, 1), rgb(&BLACK));
+        assert_eq!(*canvas.get_pixel(5, 1), rgb(&WHITE));
+        assert_eq!(*canvas.get_pixel(9, 1), rgb(&red));
+        assert_eq!(stats.color_switches, 2);
+    }
+
+    #[test]
+    fn strokes_cover_the_same_area_as_pixels() {
+        let image = image(&[&[BLACK, BLACK, BLACK, WHITE], &[BLACK, BLACK, WHITE, BLACK]]);
+
+        let (pixels, pixel_stats) = simulate(&settings(false, Strokes::Pixels), &image);
+        let (rows, row_stats) = simulate(&settings(false, Strokes::Rows), &image);
+
+        assert_eq!(pixels, rows);
+        assert_eq!(pixel_stats.clicks, 6);
+        assert_eq!(row_stats.clicks, 3);
+        assert_eq!(row_stats.strokes, 2);
+    }
+
+    #[test]
+    fn checkerboard_fills_the_whole_canvas() {
+        let image = image(&[&[BLACK, BLACK], &[BLACK, BLACK]]);
+        let mut settings = settings(true, Strokes::Rows);
+        settings.drawing_width = 6;
+
+        let (canvas, stats) = simulate(&settings, &image);
+
+        assert!(canvas.pixels().all(|pixel| *pixel == rgb(&BLACK)));
+        assert_eq!(stats.clicks, 4);
+        assert_eq!(stats.color_switches, 1);
+    }
+
+    #[test]
+    fn bucket_fills_like_the_brush() {
+        let mut settings = settings(false, Strokes::Rows);
+        settings.drawing_width = 30;
+        settings.drawing_height = 30;
+        settings.brush_tool_x = Some(300);
+        settings.brush_tool_y = Some(20);
+        settings.fill_tool_x = Some(330);
+        settings.fill_tool_y = Some(20);
+
+        // a black square with a red hole that's too small to fill
+        let image = ImageBuffer::from_fn(9, 9, |x, y| {
+            if (3..5).contains(&x) && (3..5).contains(&y) {
+                rgb(&LIGHT_RED)
+            } else {
+                rgb(&BLACK)
+            }
+        });
+
+        let (brushed, brushed_stats) = simulate(&settings, &image);
+        settings.fill = Some(true);
+        let (filled, filled_stats) = simulate(&settings, &image);
+
+        assert!(filled == brushed);
+        assert!(filled_stats.clicks < brushed_stats.clicks);
+    }
+
+    #[test]
+    fn large_brushes_paint_the_same_picture() {
+        let mut settings = settings(true, Strokes::Rows);
+        settings.drawing_width = 45;
+        settings.drawing_height = 45;
+
+        let image = ImageBuffer::from_fn(15, 15, |x, y| match (x, y) {
+            (7, 7) => rgb(&WHITE),
+            (0..=9, _) => rgb(&BLACK),
+            _ => rgb(&LIGHT_RED),
+        });
+
+        let (small, small_stats) = simulate(&settings, &image);
+        settings.brushes = Some(vec![
+            Brush {
+                x: 300,
+                y: 20,
+                size: 3,
+            },
+            Brush {
+                x: 330,
+                y: 20,
+                size: 12,
+            },
+        ]);
+        let (large, large_stats) = simulate(&settings, &image);
+
+        assert!(large == small);
+        assert!(large_stats.clicks < small_stats.clicks);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::desktop::Desktop;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::io::AsRawFd,
+    slice,
+    sync::Mutex,
+};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+
+// ioctls from linux/uinput.h
+const UI_DEV_CREATE: libc::c_ulong = 0x5501;
+const UI_DEV_DESTROY: libc::c_ulong = 0x5502;
+const UI_SET_EVBIT: libc::c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: libc::c_ulong = 0x4004_5565;
+const UI_SET_ABSBIT: libc::c_ulong = 0x4004_5567;
+
+// event codes from linux/input-event-codes.h
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0x00;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const BTN_LEFT: u16 = 0x110;
+const KEY_LEFTSHIFT: u16 = 42;
+// highest key code returned by key_code
+const KEY_MAX_TYPED: u16 = 57;
+
+/// Virtual absolute pointer and keyboard, works without an X server.
+/// The keyboard sends key codes, not characters, so what is typed is only right
+/// if the desktop uses a US layout
+pub struct UinputDesktop {
+    file: Mutex<File>,
+}
+
+impl UinputDesktop {
+    /// The screen size is needed to map the absolute axes onto pixels
+    pub fn new(screen_width: u32, screen_height: u32) -> io::Result<Self> {
+        let file = OpenOptions::new().write(true).open(UINPUT_PATH)?;
+        let fd = file.as_raw_fd();
+
+        let mut user_dev: libc::uinput_user_dev = unsafe { mem::zeroed() };
+        for (dst, src) in user_dev.name.iter_mut().zip(b"skribbliobot".iter()) {
+            *dst = *src as _;
+        }
+        user_dev.id.bustype = 0x06; // BUS_VIRTUAL
+        user_dev.absmax[ABS_X as usize] = screen_width.saturating_sub(1) as i32;
+        user_dev.absmax[ABS_Y as usize] = screen_height.saturating_sub(1) as i32;
+
+        unsafe {
+            ioctl(fd, UI_SET_EVBIT, EV_KEY)?;
+            ioctl(fd, UI_SET_EVBIT, EV_ABS)?;
+            ioctl(fd, UI_SET_ABSBIT, ABS_X)?;
+            ioctl(fd, UI_SET_ABSBIT, ABS_Y)?;
+            ioctl(fd, UI_SET_KEYBIT, BTN_LEFT)?;
+            for code in 1..=KEY_MAX_TYPED {
+                ioctl(fd, UI_SET_KEYBIT, code)?;
+            }
+
+            let bytes = slice::from_raw_parts(
+                &user_dev as *const _ as *const u8,
+                mem::size_of::<libc::uinput_user_dev>(),
+            );
+            (&file).write_all(bytes)?;
+
+            ioctl(fd, UI_DEV_CREATE, 0)?;
+        }
+
+        Ok(UinputDesktop {
+            file: Mutex::new(file),
+        })
+    }
+
+    fn emit(&self, events: &[(u16, u16, i32)]) {
+        let mut file = self.file.lock().unwrap();
+
+        for &(type_, code, value) in events.iter().chain(&[(EV_SYN, SYN_REPORT, 0)]) {
+            let event = libc::input_event {
+                time: libc::timeval {
+                    tv_sec: 0,
+                    tv_usec: 0,
+                },
+                type_,
+                code,
+                value,
+            };
+
+            let bytes = unsafe {
+                slice::from_raw_parts(
+                    &event as *const _ as *const u8,
+                    mem::size_of::<libc::input_event>(),
+                )
+            };
+
+            if let Err(err) = file.write_all(bytes) {
+                println!("Failed to write uinput event: {}", err);
+                return;
+            }
+        }
+    }
+
+    fn tap_key(&self, code: u16, shift: bool) {
+        if shift {
+            self.emit(&[(EV_KEY, KEY_LEFTSHIFT, 1)]);
+        }
+
+        self.emit(&[(EV_KEY, code, 1)]);
+        self.emit(&[(EV_KEY, code, 0)]);
+
+        if shift {
+            self.emit(&[(EV_KEY, KEY_LEFTSHIFT, 0)]);
+        }
+    }
+}
+
+impl Desktop for UinputDesktop {
+    fn move_cursor(&self, x: u32, y: u32) {
+        self.emit(&[(EV_ABS, ABS_X, x as i32), (EV_ABS, ABS_Y, y as i32)]);
+    }
+
+    fn press(&self) {
+        self.emit(&[(EV_KEY, BTN_LEFT, 1)]);
+    }
+
+    fn release(&self) {
+        self.emit(&[(EV_KEY, BTN_LEFT, 0)]);
+    }
+
+    fn type_key(&self, key: char) {
+        if let Some((code, shift)) = key_code(key) {
+            self.tap_key(code, shift);
+        }
+    }
+}
+
+impl Drop for UinputDesktop {
+    fn drop(&mut self) {
+        let fd = self.file.lock().unwrap().as_raw_fd();
+
+        unsafe {
+            let _ = ioctl(fd, UI_DEV_DESTROY, 0);
+        }
+    }
+}
+
+unsafe fn ioctl(fd: libc::c_int, request: libc::c_ulong, arg: u16) -> io::Result<()> {
+    if libc::ioctl(fd, request as _, libc::c_int::from(arg)) < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// linux key code and whether shift is needed, assuming a US layout,
+// uinput can't see the layout the desktop actually uses
+fn key_code(key: char) -> Option<(u16, bool)> {
+    const NUMBER_ROW: &[u8] = b"1234567890-=";
+    const TOP_ROW: &[u8] = b"qwertyuiop[]";
+    const HOME_ROW: &[u8] = b"asdfghjkl;'";
+    const BOTTOM_ROW: &[u8] = b"zxcvbnm,./";
+    const SHIFTED_NUMBER_ROW: &[u8] = b"!@#$%^&*()_+";
+
+    let shift = key.is_ascii_uppercase();
+    let lower = key.to_ascii_lowercase() as u8;
+
+    let find = |row: &[u8], first_code: u16| {
+        row.iter()
+            .position(|&c| c == lower)
+            .map(|index| first_code + index as u16)
+    };
+
+    match key {
+        '\n' => Some((28, false)),
+        ' ' => Some((57, false)),
+        _ if !key.is_ascii() => None,
+        _ => find(NUMBER_ROW, 2)
+            .or_else(|| find(TOP_ROW, 16))
+            .or_else(|| find(HOME_ROW, 30))
+            .or_else(|| find(BOTTOM_ROW, 44))
+            .map(|code| (code, shift))
+            .or_else(|| find(SHIFTED_NUMBER_ROW, 2).map(|code| (code, true))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_codes() {
+        assert_eq!(key_code('1'), Some((2, false)));
+        assert_eq!(key_code('0'), Some((11, false)));
+        assert_eq!(key_code('a'), Some((30, false)));
+        assert_eq!(key_code('F'), Some((33, true)));
+        assert_eq!(key_code('#'), Some((4, true)));
+        assert_eq!(key_code('\n'), Some((28, false)));
+        assert_eq!(key_code('ä'), None);
+    }
+}
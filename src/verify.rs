@@ -0,0 +1,94 @@
+use crate::{
+    calibration,
+    colors::{Color, WHITE},
+    image_converter::Image,
+};
+
+/// Pixels of the converted image that don't have their color on a capture of the canvas.
+/// `position` is where the center of a pixel is on the capture, None if it isn't drawn.
+/// White pixels are skipped, the drawer never draws them.
+pub fn wrong_pixels<F>(target: &Image, capture: &Image, position: F) -> Vec<(u32, u32)>
+where
+    F: Fn(u32, u32) -> Option<(u32, u32)>,
+{
+    target
+        .enumerate_pixels()
+        .filter(|&(x, y, pixel)| {
+            let color = Color {
+                r: pixel[0],
+                g: pixel[1],
+                b: pixel[2],
+            };
+            if color == WHITE {
+                return false;
+            }
+
+            match position(x, y) {
+                Some((capture_x, capture_y))
+                    if capture_x < capture.width() && capture_y < capture.height() =>
+                {
+                    !calibration::matches(capture.get_pixel(capture_x, capture_y), &color)
+                }
+                _ => false,
+            }
+        })
+        .map(|(x, y, _)| (x, y))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::{BLACK, LIGHT_RED};
+    use image::{ImageBuffer, Rgb};
+
+    fn rgb(color: &Color) -> Rgb<u8> {
+        Rgb([color.r, color.g, color.b])
+    }
+
+    // every pixel of the target covers 3x3 pixels of the capture
+    fn centers(x: u32, y: u32) -> Option<(u32, u32)> {
+        Some((x * 3 + 1, y * 3 + 1))
+    }
+
+    #[test]
+    fn finds_pixels_that_were_not_drawn() {
+        let target = ImageBuffer::from_fn(3, 2, |x, y| match (x, y) {
+            (0, _) => rgb(&BLACK),
+            (1, 0) => rgb(&WHITE),
+            _ => rgb(&LIGHT_RED),
+        });
+
+        // the drawing without the click on (2, 1) and with a slightly different red
+        let capture = ImageBuffer::from_fn(9, 6, |x, y| match (x / 3, y / 3) {
+            (2, 1) => rgb(&WHITE),
+            (1, 1) | (2, 0) => Rgb([LIGHT_RED.r - 3, LIGHT_RED.g + 2, LIGHT_RED.b]),
+            (x, y) => *target.get_pixel(x, y),
+        });
+
+        assert_eq!(wrong_pixels(&target, &capture, centers), vec![(2, 1)]);
+        assert_eq!(
+            wrong_pixels(&target, &target, |x, y| Some((x, y))),
+            Vec::new()
+        );
+    }
+
+    #[test]
+    fn skips_pixels_outside_of_the_capture() {
+        let target = ImageBuffer::from_pixel(4, 1, rgb(&BLACK));
+        let capture = ImageBuffer::from_pixel(6, 3, rgb(&WHITE));
+
+        assert_eq!(
+            wrong_pixels(&target, &capture, centers),
+            vec![(0, 0), (1, 0)]
+        );
+        assert_eq!(
+            wrong_pixels(&target, &capture, |x, _| if x == 0 {
+                None
+            } else {
+                Some((0, 0))
+            }),
+            vec![(1, 0), (2, 0), (3, 0)]
+        );
+    }
+}